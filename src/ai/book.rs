@@ -0,0 +1,180 @@
+use crate::data::{LargeField, Mark, FieldPosition, Move, Slot, format_move, parse_move};
+use crate::ai::{calc_move, EngineSettings};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, ErrorKind};
+use std::path::Path;
+use std::sync::OnceLock;
+use rand::{thread_rng, Rng};
+use rand::seq::IteratorRandom;
+use rand::distributions::WeightedIndex;
+
+/// The book used by the AI, unless `TICKTACKTOE_BOOK` points somewhere else.
+pub const DEFAULT_BOOK_PATH: &str = "opening_book.txt";
+
+/// Maps position keys to weighted moves.
+///
+/// The file format is line based: `<position key in hex> <move> <weight>`,
+/// lines starting with `#` are comments.
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    entries: HashMap<u64, Vec<(Move, u32)>>,
+}
+
+impl OpeningBook {
+    /// The book loaded at the first use, `None` if there is no book file or it is empty.
+    pub fn global() -> Option<&'static OpeningBook> {
+        static BOOK: OnceLock<Option<OpeningBook>> = OnceLock::new();
+
+        BOOK.get_or_init(||{
+            let path = std::env::var("TICKTACKTOE_BOOK")
+                .unwrap_or_else(|_|String::from(DEFAULT_BOOK_PATH));
+
+            match OpeningBook::load(&path) {
+                Ok(book) if book.is_empty() => None,
+                Ok(book) => Some(book),
+                Err(err) if err.kind() == ErrorKind::NotFound => None,
+                Err(err) => {
+                    eprintln!("could not load opening book {}: {}", path, err);
+                    None
+                }
+            }
+        }).as_ref()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut book = OpeningBook::default();
+
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = ||io::Error::new(ErrorKind::InvalidData, format!("invalid entry in line {}", number + 1));
+            let mut parts = line.split_whitespace();
+
+            let key = parts.next()
+                .and_then(|key|u64::from_str_radix(key, 16).ok())
+                .ok_or_else(invalid)?;
+            let mv = parts.next()
+                .and_then(parse_move)
+                .ok_or_else(invalid)?;
+            let weight = parts.next()
+                .and_then(|weight|weight.parse().ok())
+                .ok_or_else(invalid)?;
+
+            book.add(key, mv, weight);
+        }
+
+        Ok(book)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "# position key, move, weight")?;
+
+        let mut keys: Vec<_> = self.entries.keys().collect();
+        keys.sort();
+        for key in keys {
+            for (mv, weight) in &self.entries[key] {
+                writeln!(file, "{:016x} {} {}", key, format_move(*mv), weight)?;
+            }
+        }
+
+        file.flush()
+    }
+
+    pub fn add(&mut self, key: u64, mv: Move, weight: u32) {
+        let moves = self.entries.entry(key).or_default();
+
+        if let Some(entry) = moves.iter_mut().find(|(m, _)|*m == mv) {
+            entry.1 += weight;
+        } else {
+            moves.push((mv, weight));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Chooses one of the legal book moves for this position, weighted randomly.
+    pub fn pick(&self, field: &LargeField, mark: Mark, next_field: Option<FieldPosition>) -> Option<Move> {
+        let moves: Vec<_> = self.entries.get(&field.position_key(mark, next_field))?
+            .iter()
+            .filter(|(mv, weight)|*weight > 0 && field.is_legal(next_field, *mv))
+            .collect();
+
+        let index = WeightedIndex::new(moves.iter().map(|(_, weight)|*weight)).ok()?;
+
+        Some(moves[thread_rng().sample(index)].0)
+    }
+
+    /// Builds a book from self-play games, searching `ahead` moves deep for each of the first `plies` moves.
+    ///
+    /// Every move is replaced by a random one with the probability `exploration`, so the games
+    /// differ from each other. Random moves are played but not recorded.
+    pub fn generate(games: usize, ahead: u64, plies: usize, exploration: f64) -> Self {
+        let mut book = OpeningBook::default();
+        let mut rng = thread_rng();
+
+        for game in 0..games {
+            let mut field = LargeField::empty();
+            let mut mark = Mark::Cross;
+            let mut next_field = None;
+
+            for _ in 0..plies {
+                if !field.has_free() {
+                    break;
+                }
+
+                let mv = if rng.gen_bool(exploration) {
+                    field.legal_moves(next_field).choose(&mut rng).unwrap()
+                } else {
+                    let mv = calc_move(field, mark, next_field, &EngineSettings::exact(ahead * 2));
+                    book.add(field.position_key(mark, next_field), mv, 1);
+                    mv
+                };
+
+                next_field = field.play(mv, mark);
+                mark = mark.other();
+            }
+
+            eprintln!("game {}/{}: {} positions", game + 1, games, book.len());
+        }
+
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_the_book() {
+        let start = LargeField::empty();
+        let mut book = OpeningBook::default();
+        book.add(start.position_key(Mark::Cross, None), parse_move("55").unwrap(), 3);
+        book.add(start.position_key(Mark::Cross, None), parse_move("11").unwrap(), 1);
+        book.add(start.position_key(Mark::Cross, None), parse_move("55").unwrap(), 2);
+        book.add(0xdead_beef, parse_move("99").unwrap(), 7);
+
+        let path = std::env::temp_dir().join(format!("ticktacktoe_book_{}.txt", std::process::id()));
+        book.save(&path).unwrap();
+        let loaded = OpeningBook::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.entries, book.entries);
+        assert_eq!(loaded.entries[&start.position_key(Mark::Cross, None)], vec![(parse_move("55").unwrap(), 5), (parse_move("11").unwrap(), 1)]);
+        assert!(loaded.pick(&start, Mark::Cross, None).is_some());
+    }
+}
@@ -0,0 +1,356 @@
+use crate::data::{LargeField, Mark, FieldPosition, Slot, Move};
+use crate::ai::book::OpeningBook;
+use crate::ai::solver::{Solution, Outcome, solve, is_endgame, NODE_BUDGET, NODES_PER_MILLI};
+use crate::ai::table::{TranspositionTable, Entry, Bound};
+use itertools::Itertools;
+use std::cmp::Ordering;
+use std::time::{Instant, Duration};
+use std::thread::{sleep, spawn};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use druid::{ExtEventSink, Selector, Target, Data};
+use rand::{thread_rng, Rng};
+use rand::distributions::WeightedIndex;
+
+pub mod book;
+pub mod solver;
+pub mod table;
+
+fn game_state(field: LargeField, mark: Mark) -> f32 {
+    let won_field = FieldPosition::all()
+        .map(|pos|field[pos].belongs_to().map(|m|m==mark))
+        .filter_map(|x|x)
+        .fold(0.0, |state, x|if x {state + 1.0} else {state - 1.0});
+
+    let tactic_position: f32 = FieldPosition::all()
+        .filter(|&pos|{
+            // Won fields may stay open, but they can't be won again.
+            field[pos].has_free() && field[pos].belongs_to().is_none()
+        })
+        .cartesian_product(FieldPosition::all())
+        .filter(|(outer, inner)|field[*outer][*inner].has_free())
+        .map(|(outer, inner_pos)|{
+            let wins = |mark|{
+                let mut inner = field[outer];
+                inner.set(inner_pos, Some(mark));
+                inner.belongs_to().is_some()
+            };
+            let x = if wins(mark) {1.0} else {0.0};
+            x + if wins(mark.other()) {-1.0} else {0.0}
+        })
+        .sum();
+
+    let won = match field.belongs_to().map(|m|m==mark) {
+        Some(true) => f32::INFINITY,
+        Some(false) => f32::NEG_INFINITY,
+        None => 0.0,
+    };
+
+    won + won_field + tactic_position * 0.3
+}
+
+/// The score of a won game, faster wins get a bonus for the remaining search depth.
+const WIN: f32 = 10000.0;
+
+pub const MIN_LEVEL: u64 = 1;
+pub const MAX_LEVEL: u64 = 10;
+/// The lowest level using the opening book and the endgame solver.
+const MIN_INSTANT_LEVEL: u64 = 7;
+
+#[derive(Copy, Clone, PartialEq, Debug, Data)]
+pub struct EngineSettings {
+    /// The number of plies searched at most.
+    pub depth: u64,
+    /// Stops deepening the search after this many milliseconds.
+    pub time_limit: Option<u64>,
+    /// Moves are chosen by a softmax over their scores, 0.0 always plays the best move.
+    pub temperature: f64,
+    /// Plays the moves of the opening book and the endgame solver without searching.
+    pub instant_moves: bool,
+}
+
+impl EngineSettings {
+    /// The settings for the difficulty levels `MIN_LEVEL` to `MAX_LEVEL`.
+    pub fn preset(level: u64) -> Self {
+        let (depth, time_limit, temperature) = match level.clamp(MIN_LEVEL, MAX_LEVEL) {
+            1 => (1, 200, 3.0),
+            2 => (1, 200, 1.5),
+            3 => (2, 300, 1.0),
+            4 => (2, 500, 0.6),
+            5 => (3, 800, 0.4),
+            6 => (3, 1000, 0.2),
+            7 => (4, 1500, 0.1),
+            8 => (4, 2000, 0.05),
+            9 => (5, 3000, 0.0),
+            _ => (6, 5000, 0.0),
+        };
+
+        EngineSettings {
+            depth,
+            time_limit: Some(time_limit),
+            temperature,
+            // Book openings and perfect endgames would be too strong for beginners.
+            instant_moves: level >= MIN_INSTANT_LEVEL,
+        }
+    }
+
+    /// Always searches `depth` plies and plays the best move.
+    pub fn exact(depth: u64) -> Self {
+        EngineSettings {
+            depth,
+            time_limit: None,
+            temperature: 0.0,
+            instant_moves: true,
+        }
+    }
+
+    /// How many positions the endgame solver may visit within the time limit.
+    pub fn node_budget(&self) -> u64 {
+        self.time_limit.map_or(NODE_BUDGET, |limit|limit.saturating_mul(NODES_PER_MILLI))
+    }
+}
+
+/// A move found by the search with the id, see `new_search`.
+pub static MAKE_MOVE: Selector<(u64, Move)> = Selector::new("de.ticktacktoe_ultimate.make_move");
+
+static SEARCH_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The id for the moves of a new search. The moves of all earlier searches are
+/// outdated from now on, as the game or the players changed since they started.
+pub fn new_search() -> u64 {
+    SEARCH_ID.fetch_add(1, AtomicOrdering::SeqCst) + 1
+}
+
+/// Whether the move of the search with `id` is still wanted.
+pub fn is_current_search(id: u64) -> bool {
+    SEARCH_ID.load(AtomicOrdering::SeqCst) == id
+}
+
+/// The solved position, identified by its position key.
+pub static ANALYSIS: Selector<(u64, Solution)> = Selector::new("de.ticktacktoe_ultimate.analysis");
+
+/// Searches a move in the background and submits it as `MAKE_MOVE` with the id `search`,
+/// not before `delay` passed.
+pub fn best_move(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, settings: EngineSettings, delay: Duration, search: u64, sink: ExtEventSink) {
+    stop_pondering();
+
+    spawn(move ||{
+        let start = Instant::now();
+
+        let ret = instant_move(field, mark, next_field, &settings)
+            .unwrap_or_else(||calc_move(field, mark, next_field, &settings));
+
+        if let Some(duration) = delay.checked_sub(start.elapsed()) {
+            sleep(duration);
+        }
+        sink.submit_command(MAKE_MOVE, (search, ret), Target::Global).unwrap();
+    });
+}
+
+/// A move from the opening book or the endgame solver, if they know the position
+/// and `settings` allow instant moves.
+pub fn instant_move(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, settings: &EngineSettings) -> Option<Move> {
+    if !settings.instant_moves {
+        return None;
+    }
+    OpeningBook::global()
+        .and_then(|book|book.pick(&field, mark, next_field))
+        .or_else(||endgame_move(field, mark, next_field, settings.node_budget()))
+}
+
+fn endgame_move(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, node_budget: u64) -> Option<Move> {
+    if is_endgame(&field) {
+        solve(field, mark, next_field, node_budget)?.best
+    } else {
+        None
+    }
+}
+
+/// Whether the AI playing `mark` agrees to a draw, it does unless it expects to win.
+pub fn accepts_draw(field: LargeField, mark: Mark, next_turn: Mark, next_field: Option<FieldPosition>) -> bool {
+    if is_endgame(&field) {
+        if let Some(solution) = solve(field, next_turn, next_field, NODE_BUDGET) {
+            let winning = if next_turn == mark {Outcome::Win} else {Outcome::Loss};
+            return solution.outcome != winning;
+        }
+    }
+    game_state(field, mark) <= 0.0
+}
+
+/// Tries to solve the position in the background and reports the result as `ANALYSIS`.
+pub fn analyse(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, sink: ExtEventSink) {
+    if field.belongs_to().is_some() || !is_endgame(&field) {
+        return;
+    }
+
+    spawn(move ||{
+        if let Some(solution) = solve(field, mark, next_field, NODE_BUDGET) {
+            let key = field.position_key(mark, next_field);
+            sink.submit_command(ANALYSIS, (key, solution), Target::Global).unwrap();
+        }
+    });
+}
+
+/// Ends a search when the deadline passed or the stop flag is set.
+#[derive(Clone, Default)]
+pub struct SearchLimit {
+    pub deadline: Option<Instant>,
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl SearchLimit {
+    pub fn reached(&self) -> bool {
+        self.deadline.is_some_and(|deadline|Instant::now() >= deadline) ||
+            self.stop.as_ref().is_some_and(|stop|stop.load(AtomicOrdering::Relaxed))
+    }
+}
+
+pub fn calc_move(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, settings: &EngineSettings) -> Move {
+    let limit = SearchLimit {
+        deadline: settings.time_limit.map(|limit|Instant::now() + Duration::from_millis(limit)),
+        stop: None,
+    };
+    let scores = score_moves(field, mark, next_field, settings.depth, &limit);
+
+    choose_move(&scores, settings.temperature)
+}
+
+/// Scores every legal move, deepening the search until `depth` or the limit is reached.
+pub fn score_moves(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, depth: u64, limit: &SearchLimit) -> Vec<(Move, f32)> {
+    let mut scores = score_moves_at(field, mark, next_field, 1, &SearchLimit::default())
+        .expect("a search without limit always finishes");
+
+    for depth in 2..=depth {
+        match score_moves_at(field, mark, next_field, depth, limit) {
+            Some(new_scores) => scores = new_scores,
+            None => break,
+        }
+    }
+
+    scores
+}
+
+/// Scores every legal move with a search of `depth` plies, `None` if the limit was reached.
+pub fn score_moves_at(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, depth: u64, limit: &SearchLimit) -> Option<Vec<(Move, f32)>> {
+    field.legal_moves(next_field)
+        .map(|mv|{
+            let mut new_field = field;
+            let next_pos = new_field.play(mv, mark);
+
+            let score = -negamax(new_field, mark.other(), next_pos, depth - 1, f32::NEG_INFINITY, f32::INFINITY, limit)?;
+            Some((mv, score))
+        })
+        .collect()
+}
+
+/// Scores the position for `mark`, the player to move. `None` if the limit was reached.
+fn negamax(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, depth: u64, mut alpha: f32, mut beta: f32, limit: &SearchLimit) -> Option<f32> {
+    if field.belongs_to().is_some() {
+        // The previous move finished the game.
+        return Some(-WIN - depth as f32);
+    }
+    if !field.has_free() {
+        return Some(0.0);
+    }
+    if depth == 0 {
+        return Some(game_state(field, mark));
+    }
+    if limit.reached() {
+        return None;
+    }
+
+    let key = field.position_key(mark, next_field);
+    let known = TranspositionTable::global().get(key);
+    if let Some(entry) = known.filter(|entry|entry.depth >= depth) {
+        match entry.bound {
+            Bound::Exact => return Some(entry.score),
+            Bound::Lower => alpha = alpha.max(entry.score),
+            Bound::Upper => beta = beta.min(entry.score),
+        }
+        if alpha >= beta {
+            return Some(entry.score);
+        }
+    }
+
+    // The best move of an earlier search is tried first, it most likely causes a cutoff.
+    // The entry may belong to another position with the same key, so the move is checked.
+    let hint = known.and_then(|entry|entry.best).filter(|mv|field.is_legal(next_field, *mv));
+    let moves = hint.into_iter()
+        .chain(field.legal_moves(next_field).filter(|mv|Some(*mv) != hint));
+
+    let alpha_start = alpha;
+    let mut best = (f32::NEG_INFINITY, None);
+
+    for mv in moves {
+        let mut new_field = field;
+        let next_pos = new_field.play(mv, mark);
+
+        let score = -negamax(new_field, mark.other(), next_pos, depth - 1, -beta, -alpha, limit)?;
+        if score > best.0 || best.1.is_none() {
+            best = (score, Some(mv));
+        }
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    let bound = if best.0 <= alpha_start {
+        Bound::Upper
+    } else if best.0 >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    };
+    TranspositionTable::global().insert(key, Entry {depth, score: best.0, bound, best: best.1});
+
+    Some(best.0)
+}
+
+/// Searches the position while the player thinks, until `stop_pondering` is called.
+///
+/// The results end up in the transposition table, the search after the actual move
+/// of the player finds them there.
+pub fn ponder(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, settings: EngineSettings) {
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(old) = PONDERING.lock().unwrap().replace(stop.clone()) {
+        old.store(true, AtomicOrdering::Relaxed);
+    }
+
+    spawn(move ||{
+        let limit = SearchLimit {
+            deadline: None,
+            stop: Some(stop),
+        };
+        // One ply deeper, so the replies are searched as deep as the AI would search them.
+        score_moves(field, mark, next_field, settings.depth + 1, &limit);
+    });
+}
+
+pub fn stop_pondering() {
+    if let Some(stop) = PONDERING.lock().unwrap().take() {
+        stop.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+static PONDERING: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+
+/// Picks a move by a softmax over the scores, the best one if `temperature` is zero.
+pub fn choose_move(scores: &[(Move, f32)], temperature: f64) -> Move {
+    let (best_move, best_score) = scores.iter()
+        .copied()
+        .max_by(|v0, v1| v0.1.partial_cmp(&v1.1).unwrap_or(Ordering::Equal))
+        .expect("there is no legal move");
+
+    if temperature <= 0.0 {
+        return best_move;
+    }
+
+    let weights = scores.iter()
+        .map(|(_, score)|((*score - best_score) as f64 / temperature).exp());
+
+    match WeightedIndex::new(weights) {
+        Ok(index) => scores[thread_rng().sample(index)].0,
+        Err(_) => best_move,
+    }
+}
@@ -0,0 +1,234 @@
+use crate::data::{LargeField, Mark, FieldPosition, Move, Slot};
+use druid::Data;
+use std::collections::HashMap;
+
+/// Positions with at most this many empty slots in playable fields are handed to the solver.
+pub const ENDGAME_SLOTS: usize = 24;
+
+/// How many positions the solver may visit before giving up.
+pub const NODE_BUDGET: u64 = 300_000;
+/// About how many positions the solver visits per millisecond in a release build.
+pub const NODES_PER_MILLI: u64 = 1000;
+
+/// The result of perfect play, seen from the player to move.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub struct Solution {
+    pub outcome: Outcome,
+    /// The number of plies until the game ends with perfect play of both sides.
+    pub plies: u32,
+    pub best: Option<Move>,
+}
+
+impl Solution {
+    /// The number of moves the winning side needs to finish the game.
+    pub fn moves(&self) -> u32 {
+        match self.outcome {
+            Outcome::Loss => self.plies / 2,
+            _ => self.plies.div_ceil(2),
+        }
+    }
+}
+
+pub fn empty_slots(field: &LargeField) -> usize {
+    FieldPosition::all()
+        .filter(|&pos|field[pos].has_free())
+        .map(|pos|FieldPosition::all().filter(|&slot|field[pos][slot].has_free()).count())
+        .sum()
+}
+
+pub fn is_endgame(field: &LargeField) -> bool {
+    empty_slots(field) <= ENDGAME_SLOTS
+}
+
+/// Solves the position exactly, `None` if more than `node_budget` positions would be needed.
+pub fn solve(field: LargeField, mark: Mark, next_field: Option<FieldPosition>, node_budget: u64) -> Option<Solution> {
+    let mut solver = Solver {
+        nodes: 0,
+        budget: node_budget,
+        known: HashMap::new(),
+    };
+    let (score, best) = solver.search(field, mark, next_field, 0, -WIN, WIN)?;
+
+    let (outcome, plies) = if score > 0 {
+        (Outcome::Win, WIN - score)
+    } else if score < 0 {
+        (Outcome::Loss, WIN + score)
+    } else {
+        (Outcome::Draw, 0)
+    };
+
+    Some(Solution {outcome, plies: plies as u32, best})
+}
+
+/// The score of a won game, reduced by the number of plies it takes to win.
+const WIN: i32 = 1000;
+
+#[derive(Copy, Clone)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+struct Solver {
+    nodes: u64,
+    budget: u64,
+    /// Scores are stored relative to the position, not to the root.
+    known: HashMap<u64, (i32, Bound, Option<Move>)>,
+}
+
+fn to_known(score: i32, ply: i32) -> i32 {
+    if score > 0 {
+        score + ply
+    } else if score < 0 {
+        score - ply
+    } else {
+        0
+    }
+}
+
+fn from_known(score: i32, ply: i32) -> i32 {
+    if score > 0 {
+        score - ply
+    } else if score < 0 {
+        score + ply
+    } else {
+        0
+    }
+}
+
+impl Solver {
+    fn search(&mut self, field: LargeField, mark: Mark, next_field: Option<FieldPosition>, ply: i32, mut alpha: i32, mut beta: i32) -> Option<(i32, Option<Move>)> {
+        if field.belongs_to().is_some() {
+            // The previous move finished the game.
+            return Some((ply - WIN, None));
+        }
+        if !field.has_free() {
+            return Some((0, None));
+        }
+
+        let key = field.position_key(mark, next_field);
+        let known = self.known.get(&key).copied();
+        if let Some((score, bound, best)) = known {
+            let score = from_known(score, ply);
+            match bound {
+                Bound::Exact => return Some((score, best)),
+                Bound::Lower => alpha = alpha.max(score),
+                Bound::Upper => beta = beta.min(score),
+            }
+            if alpha >= beta {
+                return Some((score, best));
+            }
+        }
+
+        self.nodes += 1;
+        if self.nodes > self.budget {
+            return None;
+        }
+
+        // Try the previously best move first, then moves finishing a small field.
+        let mut moves: Vec<_> = field.legal_moves(next_field)
+            .map(|mv|{
+                let mut new_field = field;
+                let next_pos = new_field.play(mv, mark);
+                let priority = if Some(mv) == known.and_then(|k|k.2) {
+                    0
+                } else if field[mv.0].belongs_to().is_none() && new_field[mv.0].belongs_to().is_some() {
+                    1
+                } else {
+                    2
+                };
+                (priority, mv, new_field, next_pos)
+            })
+            .collect();
+        moves.sort_by_key(|entry|entry.0);
+
+        let alpha_start = alpha;
+        let mut best = (-WIN, None);
+
+        for (_, mv, new_field, next_pos) in moves {
+            let score = -self.search(new_field, mark.other(), next_pos, ply + 1, -beta, -alpha)?.0;
+
+            if score > best.0 || best.1.is_none() {
+                best = (score, Some(mv));
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best.0 <= alpha_start {
+            Bound::Upper
+        } else if best.0 >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.known.insert(key, (to_known(best.0, ply), bound, best.1));
+
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Position, parse_move};
+
+    /// A full small field without a winner.
+    const DRAWN: &str = "xoxxoooxx";
+
+    fn solve_position(text: &str, node_budget: u64) -> Option<Solution> {
+        let position: Position = text.parse().unwrap();
+        solve(position.game, position.next_turn, position.next_field, node_budget)
+    }
+
+    #[test]
+    fn finds_a_win() {
+        // Cross owns the two left fields of the top row and completes the third one.
+        let text = format!("xxx....../xxx....../xx.oxoxoo/{0}/{0}/{0}/{0}/{0}/{0} x 3", DRAWN);
+        let solution = solve_position(&text, NODE_BUDGET).unwrap();
+
+        assert_eq!(solution.outcome, Outcome::Win);
+        assert_eq!(solution.plies, 1);
+        assert_eq!(solution.moves(), 1);
+        assert_eq!(solution.best, parse_move("33"));
+    }
+
+    #[test]
+    fn finds_a_loss() {
+        // The only move of Cross sends Circle to the field completing the middle row.
+        let text = format!("xoxoo.xxo/{0}/{0}/ooo....../ooo....../oo.xxoxox/{0}/{0}/{0} x 1", DRAWN);
+        let solution = solve_position(&text, NODE_BUDGET).unwrap();
+
+        assert_eq!(solution.outcome, Outcome::Loss);
+        assert_eq!(solution.plies, 2);
+        assert_eq!(solution.moves(), 1);
+        assert_eq!(solution.best, parse_move("16"));
+    }
+
+    #[test]
+    fn finds_a_draw() {
+        let text = format!("{0}/{0}/{0}/{0}/{0}/{0}/{0}/{0}/xoxxooox. x 9", DRAWN);
+        let solution = solve_position(&text, NODE_BUDGET).unwrap();
+
+        assert_eq!(solution.outcome, Outcome::Draw);
+        assert_eq!(solution.plies, 0);
+        assert_eq!(solution.moves(), 0);
+        assert_eq!(solution.best, parse_move("99"));
+    }
+
+    #[test]
+    fn gives_up_beyond_the_budget() {
+        assert_eq!(solve(LargeField::empty(), Mark::Cross, None, NODE_BUDGET), None);
+        assert!(solve_position(&format!("{0}/{0}/{0}/{0}/{0}/{0}/{0}/{0}/xoxxooox. x 9", DRAWN), 0).is_none());
+    }
+}
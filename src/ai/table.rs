@@ -0,0 +1,58 @@
+use crate::data::Move;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// The table is cleared when it grows beyond this many positions.
+const CAPACITY: usize = 1 << 20;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Bound {
+    Exact,
+    /// The score is at least this high.
+    Lower,
+    /// The score is at most this high.
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    /// The remaining depth the position was searched with.
+    pub depth: u64,
+    pub score: f32,
+    pub bound: Bound,
+    pub best: Option<Move>,
+}
+
+/// Remembers searched positions, shared between the searches of the AI and pondering.
+#[derive(Default)]
+pub struct TranspositionTable {
+    entries: HashMap<u64, Entry>,
+}
+
+impl TranspositionTable {
+    pub fn global() -> MutexGuard<'static, TranspositionTable> {
+        static TABLE: OnceLock<Mutex<TranspositionTable>> = OnceLock::new();
+
+        TABLE.get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned|poisoned.into_inner())
+    }
+
+    pub fn get(&self, key: u64) -> Option<Entry> {
+        self.entries.get(&key).copied()
+    }
+
+    /// Stores the entry, unless a deeper search of the position is known.
+    pub fn insert(&mut self, key: u64, entry: Entry) {
+        if self.entries.len() >= CAPACITY {
+            self.entries.clear();
+        }
+
+        match self.entries.get(&key) {
+            Some(old) if old.depth > entry.depth => {}
+            _ => {
+                self.entries.insert(key, entry);
+            }
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! A small HTTP server with a JSON interface, so bots can play against each other.
+//!
+//! Endpoints:
+//! - `POST /games`: starts a new game, answered with its state
+//! - `GET /games/<id>`: the state of a game
+//! - `POST /games/<id>/moves` with a body like `{"move": "55"}`: plays the move for the
+//!   mark to move, answered with the new state
+//!
+//! The state of a game looks like
+//! `{"id": 1, "position": "...", "board": [...], "fields": [...], "next_turn": "x",
+//! "next_field": 5, "legal_moves": ["51", ...], "moves": ["55"], "status": "playing"}`.
+//! `board` holds the nine small fields as nine of `x`, `o` or `.` each, `fields` the
+//! winner of every small field, `position` the notation of `Position` and `next_field`
+//! is `null` if the move can go to any field. `status` is one of `playing`, `x_won`,
+//! `o_won` or `draw`. Moves are written as two digits, see `format_move`.
+//!
+//! Failed requests are answered with a status of 400 or above and `{"error": "..."}`.
+
+use crate::data::{Position, Move, Slot, FieldPosition, format_move, parse_move};
+use crate::network::protocol::mark_to_str;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream, SocketAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+/// The default port of the bot API.
+pub const DEFAULT_API_PORT: u16 = 4748;
+
+/// Requests with a larger body are rejected, a move needs just a few bytes.
+const MAX_BODY: usize = 64 * 1024;
+
+/// How long a client may keep the connection without sending, so idle clients don't
+/// block a thread forever.
+const READ_TIMEOUT: Duration = Duration::from_secs(if cfg!(test) {1} else {10});
+
+pub fn run(port: Option<u16>) {
+    let port = port.unwrap_or(DEFAULT_API_PORT);
+
+    match TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))) {
+        Ok(listener) => {
+            println!("bot api listening on port {}", port);
+            serve(listener);
+        }
+        Err(err) => {
+            eprintln!("could not listen on port {}: {}", port, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn serve(listener: TcpListener) {
+    let games = Arc::new(Mutex::new(Games::default()));
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let games = games.clone();
+                spawn(move ||{
+                    if let Err(err) = handle_connection(stream, games) {
+                        eprintln!("request failed: {}", err);
+                    }
+                });
+            }
+            Err(err) => eprintln!("could not accept a client: {}", err),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Games {
+    games: HashMap<u64, Game>,
+    next_id: u64,
+}
+
+struct Game {
+    position: Position,
+    moves: Vec<Move>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+/// A response with its HTTP status and the JSON body.
+type Response = (u16, String);
+
+fn handle_connection(mut stream: TcpStream, games: Arc<Mutex<Games>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let (status, body) = match read_request(&mut stream) {
+        Ok(request) => route(&request, &mut games.lock().unwrap()),
+        Err(err) if err.kind() == ErrorKind::InvalidData => error(400, &err.to_string()),
+        Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            error(408, "the request was not sent in time")
+        }
+        Err(err) => return Err(err),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason(status), body.len(), body,
+    )?;
+    stream.flush()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let invalid = |text: &str|io::Error::new(ErrorKind::InvalidData, text.to_string());
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let method = words.next().ok_or_else(||invalid("missing method"))?.to_string();
+    let path = words.next().ok_or_else(||invalid("missing path"))?.to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_|invalid("invalid content length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(invalid("the body is too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_|invalid("the body is not UTF-8"))?;
+
+    Ok(Request {method, path, body})
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        _ => "Unknown",
+    }
+}
+
+fn route(request: &Request, games: &mut Games) -> Response {
+    let parts: Vec<_> = request.path.trim_matches('/').split('/').collect();
+
+    match (request.method.as_str(), parts.as_slice()) {
+        ("POST", ["games"]) => {
+            games.next_id += 1;
+            let id = games.next_id;
+            let game = Game {position: Position::start(), moves: Vec::new()};
+            let state = state(id, &game);
+            games.games.insert(id, game);
+            (201, state)
+        }
+        ("GET", ["games", id]) => match find(games, id) {
+            Some((id, game)) => (200, state(id, game)),
+            None => error(404, "there is no such game"),
+        },
+        ("POST", ["games", id, "moves"]) => match find(games, id) {
+            Some((id, game)) => play(id, game, &request.body),
+            None => error(404, "there is no such game"),
+        },
+        (_, ["games"]) | (_, ["games", _]) | (_, ["games", _, "moves"]) => error(405, "method not allowed"),
+        _ => error(404, "unknown path"),
+    }
+}
+
+fn find<'a>(games: &'a mut Games, id: &str) -> Option<(u64, &'a mut Game)> {
+    let id = id.parse().ok()?;
+    games.games.get_mut(&id).map(|game|(id, game))
+}
+
+fn play(id: u64, game: &mut Game, body: &str) -> Response {
+    let text = match json_field(body, "move") {
+        Some(text) => text,
+        None => return error(400, "expected a body like {\"move\": \"55\"}"),
+    };
+    let mv = match parse_move(text) {
+        Some(mv) => mv,
+        None => return error(400, &format!("invalid move '{}'", text)),
+    };
+    if game.position.is_finished() {
+        return error(409, "the game is over");
+    }
+
+    match game.position.play(mv) {
+        Ok(()) => {
+            game.moves.push(mv);
+            (200, state(id, game))
+        }
+        Err(err) => error(400, &err.to_string()),
+    }
+}
+
+fn state(id: u64, game: &Game) -> String {
+    let position = &game.position;
+
+    let board = FieldPosition::all()
+        .map(|outer|{
+            let slots: String = FieldPosition::all()
+                .map(|inner|position.game[outer][inner].map_or('.', |mark|mark_to_str(mark).chars().next().unwrap()))
+                .collect();
+            json_string(&slots)
+        });
+    let fields = FieldPosition::all()
+        .map(|outer|json_string(position.game[outer].belongs_to().map_or(".", mark_to_str)));
+    let legal_moves = position.legal_moves()
+        .filter(|_|!position.is_finished())
+        .map(|mv|json_string(&format_move(mv)));
+    let moves = game.moves.iter().map(|mv|json_string(&format_move(*mv)));
+
+    let status = match position.game.belongs_to() {
+        Some(mark) => format!("{}_won", mark_to_str(mark)),
+        None if position.is_finished() => String::from("draw"),
+        None => String::from("playing"),
+    };
+    let next_field = position.next_field.map_or(String::from("null"), |field|field.to_string());
+
+    format!(
+        "{{\"id\": {}, \"position\": {}, \"board\": [{}], \"fields\": [{}], \"next_turn\": {}, \"next_field\": {}, \
+        \"legal_moves\": [{}], \"moves\": [{}], \"status\": {}}}",
+        id,
+        json_string(&position.to_string()),
+        join(board),
+        join(fields),
+        json_string(mark_to_str(position.next_turn)),
+        next_field,
+        join(legal_moves),
+        join(moves),
+        json_string(&status),
+    )
+}
+
+fn error(status: u16, text: &str) -> Response {
+    (status, format!("{{\"error\": {}}}", json_string(text)))
+}
+
+fn join(values: impl Iterator<Item=String>) -> String {
+    values.collect::<Vec<_>>().join(", ")
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Finds the value of `name` in a flat JSON object, a string or a number.
+fn json_field<'a>(json: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("\"{}\"", name);
+    let rest = json[json.find(&key)? + key.len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+
+    match rest.strip_prefix('"') {
+        Some(rest) => rest.split('"').next(),
+        None => {
+            let end = rest.find(|c: char|!c.is_ascii_digit()).unwrap_or(rest.len());
+            Some(&rest[..end])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        spawn(move ||serve(listener));
+        address
+    }
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn creates_games() {
+        let address = start();
+
+        let (status, first) = request(address, "POST", "/games", "");
+        assert_eq!(status, 201);
+        assert!(first.starts_with("{\"id\": 1, "));
+        assert!(first.contains("\"status\": \"playing\""));
+        assert!(first.contains("\"next_turn\": \"x\""));
+        assert!(first.contains("\"next_field\": null"));
+        assert!(first.contains("\"legal_moves\": [\"11\", \"12\""));
+
+        let (_, second) = request(address, "POST", "/games", "");
+        assert!(second.starts_with("{\"id\": 2, "));
+
+        let (status, fetched) = request(address, "GET", "/games/1", "");
+        assert_eq!(status, 200);
+        assert_eq!(fetched, first);
+    }
+
+    #[test]
+    fn plays_moves() {
+        let address = start();
+        request(address, "POST", "/games", "");
+
+        let (status, state) = request(address, "POST", "/games/1/moves", "{\"move\": \"55\"}");
+        assert_eq!(status, 200);
+        assert!(state.contains("\"next_turn\": \"o\""));
+        assert!(state.contains("\"next_field\": 5,"));
+        assert!(state.contains("\"moves\": [\"55\"]"));
+
+        let (_, fetched) = request(address, "GET", "/games/1", "");
+        assert_eq!(fetched, state);
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let address = start();
+        request(address, "POST", "/games", "");
+        request(address, "POST", "/games/1/moves", "{\"move\": \"55\"}");
+
+        let (status, body) = request(address, "POST", "/games/1/moves", "{\"move\": \"11\"}");
+        assert_eq!(status, 400);
+        assert_eq!(body, "{\"error\": \"illegal move 11\"}");
+
+        assert_eq!(request(address, "POST", "/games/1/moves", "{\"move\": \"x\"}").0, 400);
+        assert_eq!(request(address, "POST", "/games/1/moves", "55").0, 400);
+        assert_eq!(request(address, "GET", "/games/7", "").0, 404);
+        assert_eq!(request(address, "GET", "/boards", "").0, 404);
+        assert_eq!(request(address, "DELETE", "/games/1", "").0, 405);
+    }
+
+    #[test]
+    fn closes_idle_connections() {
+        let address = start();
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET /games/1 HTTP/1.1\r\n").unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(response.ends_with("{\"error\": \"the request was not sent in time\"}"));
+    }
+
+    #[test]
+    fn plays_a_game_to_the_end() {
+        let address = start();
+        request(address, "POST", "/games", "");
+        let mut position = Position::start();
+
+        while !position.is_finished() {
+            let mv = position.legal_moves().next().unwrap();
+            position.play(mv).unwrap();
+
+            let body = format!("{{\"move\": {}}}", format_move(mv));
+            let (status, state) = request(address, "POST", "/games/1/moves", &body);
+            assert_eq!(status, 200);
+            assert!(state.contains(&format!("\"position\": \"{}\"", position)));
+        }
+
+        let (_, state) = request(address, "GET", "/games/1", "");
+        assert!(!state.contains("\"status\": \"playing\""));
+        assert!(state.contains("\"legal_moves\": []"));
+        assert_eq!(request(address, "POST", "/games/1/moves", "{\"move\": \"55\"}").0, 409);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::data::{Position, Move, Slot, parse_move};
+use crate::svg::{Annotations, render, parse_arrow};
+use crate::export::{write_gif, write_frames, DEFAULT_FRAME_SIZE, DEFAULT_FRAME_DELAY};
+use std::fs;
+use std::path::Path;
+
+/// Plays `moves` from the start, separated by spaces or commas, and prints the board
+/// and the state of the game. Exits with 1 at the first invalid or illegal move.
+pub fn play(moves: Option<String>) {
+    let replay = replay(&moves.unwrap_or_default());
+    print_position(&replay.position);
+    replay.or_exit();
+}
+
+/// Draws the position after `moves` as SVG, with the last move marked and `arrows`
+/// like `"59-95"` on top. Written to `out`, or printed if there is none.
+pub fn svg(moves: Option<String>, arrows: Option<String>, out: Option<String>) {
+    let (position, moves) = replay(&moves.unwrap_or_default()).or_exit();
+    let arrows = split(&arrows.unwrap_or_default())
+        .map(|text|parse_arrow(text).ok_or_else(||format!("invalid arrow '{}'", text)))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|err|{
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    let svg = render(&position, &Annotations {last_move: moves.last().copied(), arrows});
+    match out {
+        Some(path) => if let Err(err) = fs::write(&path, svg) {
+            eprintln!("could not write {}: {}", path, err);
+            std::process::exit(1);
+        },
+        None => print!("{}", svg),
+    }
+}
+
+/// Draws every position of the game played with `moves` as an animated GIF, if `out`
+/// ends with `.gif`, or as PNG images in the directory `out`.
+pub fn export(moves: Option<String>, out: Option<String>, size: Option<u32>, delay: Option<u64>) {
+    let (_, moves) = replay(&moves.unwrap_or_default()).or_exit();
+    let out = out.unwrap_or_else(||String::from("game.gif"));
+    let size = size.unwrap_or(DEFAULT_FRAME_SIZE);
+
+    let result = if out.ends_with(".gif") {
+        write_gif(Path::new(&out), &moves, size, delay.unwrap_or(DEFAULT_FRAME_DELAY))
+    } else {
+        write_frames(Path::new(&out), &moves, size)
+    };
+    match result {
+        Ok(frames) => println!("wrote {} positions to {}", frames, out),
+        Err(err) => {
+            eprintln!("could not write {}: {}", out, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Splits a list of moves at spaces or commas.
+fn split(text: &str) -> impl Iterator<Item=&str> {
+    text.split(|c: char|c.is_whitespace() || c == ',').filter(|text|!text.is_empty())
+}
+
+/// The game played by `replay`, up to the first invalid or illegal move.
+struct Replay {
+    position: Position,
+    moves: Vec<Move>,
+    error: Option<String>,
+}
+
+impl Replay {
+    /// The position and the moves, exits with 1 if a move was invalid or illegal.
+    fn or_exit(self) -> (Position, Vec<Move>) {
+        if let Some(err) = self.error {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        (self.position, self.moves)
+    }
+}
+
+/// Plays `moves` from the start. On an invalid or illegal move, the position before
+/// it is kept with the error.
+fn replay(moves: &str) -> Replay {
+    let mut position = Position::start();
+    let mut played = Vec::new();
+
+    for (index, text) in split(moves).enumerate() {
+        let result = parse_move(text)
+            .ok_or_else(||format!("invalid move '{}'", text))
+            .and_then(|mv|position.play(mv).map(|()|mv).map_err(|err|err.to_string()));
+
+        match result {
+            Ok(mv) => played.push(mv),
+            Err(err) => return Replay {
+                position,
+                moves: played,
+                error: Some(format!("move {}: {}", index + 1, err)),
+            },
+        }
+    }
+    Replay {position, moves: played, error: None}
+}
+
+fn print_position(position: &Position) {
+    println!("{}", position.game.display(position.next_field));
+    println!();
+    println!("position: {}", position);
+    match position.game.belongs_to() {
+        Some(mark) => println!("status: {} won", mark),
+        None if position.is_finished() => println!("status: draw"),
+        None => match position.next_field {
+            Some(field) => println!("status: {} to move in field {}", position.next_turn, field),
+            None => println!("status: {} to move in any field", position.next_turn),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Mark;
+
+    fn moves(moves: &[&str]) -> Vec<Move> {
+        moves.iter().map(|mv|parse_move(mv).unwrap()).collect()
+    }
+
+    #[test]
+    fn splits_the_moves() {
+        assert_eq!(split(" 55, 51 ,15\t").collect::<Vec<_>>(), vec!["55", "51", "15"]);
+        assert_eq!(split(" , ").count(), 0);
+    }
+
+    #[test]
+    fn replays_the_moves() {
+        let replay = replay("55 51,15");
+
+        assert!(replay.error.is_none());
+        assert_eq!(replay.moves, moves(&["55", "51", "15"]));
+        assert_eq!(replay.position.to_string(), "....x..../........./........./........./o...x..../........./........./........./......... o 5");
+    }
+
+    #[test]
+    fn stops_at_an_invalid_move() {
+        let replay = replay("55 5x 15");
+
+        assert_eq!(replay.error.as_deref(), Some("move 2: invalid move '5x'"));
+        assert_eq!(replay.moves, moves(&["55"]));
+        assert_eq!(replay.position.next_turn, Mark::Circle);
+    }
+
+    #[test]
+    fn stops_at_an_illegal_move() {
+        // The second move has to be in field 5.
+        let replay = replay("55 15");
+
+        assert_eq!(replay.error.as_deref(), Some("move 2: illegal move 15"));
+        assert_eq!(replay.moves, moves(&["55"]));
+        assert_eq!(replay.position.next_turn, Mark::Circle);
+    }
+}
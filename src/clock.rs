@@ -0,0 +1,193 @@
+use crate::data::Mark;
+use druid::{Data, Lens};
+
+/// How the clocks of a game run.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum ClockMode {
+    Off,
+    /// Every player has `base` for the whole game and gets `increment` for every move.
+    Increment,
+    /// Every move has to be made within `move_time`.
+    PerMove,
+}
+
+impl ClockMode {
+    pub fn next(self) -> Self {
+        match self {
+            ClockMode::Off => ClockMode::Increment,
+            ClockMode::Increment => ClockMode::PerMove,
+            ClockMode::PerMove => ClockMode::Off,
+        }
+    }
+}
+
+/// The time control for the next games, `base` in minutes, the other times in seconds.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data, Lens)]
+pub struct TimeControl {
+    pub mode: ClockMode,
+    pub base: u64,
+    pub increment: u64,
+    pub move_time: u64,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl {
+            mode: ClockMode::Off,
+            base: 5,
+            increment: 3,
+            move_time: 30,
+        }
+    }
+}
+
+impl TimeControl {
+    /// The clocks for a new game, `None` if the game is played without clocks.
+    pub fn start(&self) -> Option<Clocks> {
+        let (time, increment) = match self.mode {
+            ClockMode::Off => return None,
+            ClockMode::Increment => (self.base * 60_000, self.increment * 1000),
+            ClockMode::PerMove => (self.move_time * 1000, 0),
+        };
+
+        Some(Clocks {
+            mode: self.mode,
+            increment,
+            move_time: self.move_time * 1000,
+            cross: time,
+            circle: time,
+            paused: false,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match self.mode {
+            ClockMode::Off => String::from("No clock"),
+            ClockMode::Increment => format!("{} min + {} s", self.base, self.increment),
+            ClockMode::PerMove => format!("{} s per move", self.move_time),
+        }
+    }
+}
+
+/// The clocks of a running game, all times in milliseconds.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub struct Clocks {
+    mode: ClockMode,
+    increment: u64,
+    move_time: u64,
+    cross: u64,
+    circle: u64,
+    pub paused: bool,
+}
+
+impl Clocks {
+    pub fn remaining(&self, mark: Mark) -> u64 {
+        match mark {
+            Mark::Cross => self.cross,
+            Mark::Circle => self.circle,
+        }
+    }
+
+    fn remaining_mut(&mut self, mark: Mark) -> &mut u64 {
+        match mark {
+            Mark::Cross => &mut self.cross,
+            Mark::Circle => &mut self.circle,
+        }
+    }
+
+    /// Counts down the clock of `mark`, returns whether its time ran out.
+    pub fn tick(&mut self, mark: Mark, elapsed: u64) -> bool {
+        if self.paused {
+            return false;
+        }
+        let remaining = self.remaining_mut(mark);
+        *remaining = remaining.saturating_sub(elapsed);
+        *remaining == 0
+    }
+
+    /// `mark` made a move, its clock is stopped.
+    pub fn moved(&mut self, mark: Mark) {
+        match self.mode {
+            ClockMode::Off => {}
+            ClockMode::Increment => *self.remaining_mut(mark) += self.increment,
+            ClockMode::PerMove => *self.remaining_mut(mark) = self.move_time,
+        }
+    }
+
+    /// The time `mark` should think about its next move at most.
+    pub fn budget(&self, mark: Mark) -> u64 {
+        let remaining = self.remaining(mark);
+        match self.mode {
+            ClockMode::Off => remaining,
+            // Enough for the moves likely to come, the increment is mostly spent right away.
+            ClockMode::Increment => (remaining / 25 + self.increment * 3 / 4).min(remaining / 2),
+            ClockMode::PerMove => remaining / 2,
+        }
+    }
+}
+
+/// Shows the time as `m:ss`, or with tenths of seconds when it is running out.
+pub fn format_time(millis: u64) -> String {
+    if millis < 10_000 {
+        format!("{}.{}", millis / 1000, millis % 1000 / 100)
+    } else {
+        let seconds = millis.div_ceil(1000);
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks(mode: ClockMode) -> Clocks {
+        TimeControl {mode, base: 1, increment: 2, move_time: 10}.start().unwrap()
+    }
+
+    #[test]
+    fn adds_the_increment_after_a_move() {
+        let mut clocks = clocks(ClockMode::Increment);
+        assert_eq!(clocks.remaining(Mark::Cross), 60_000);
+
+        assert!(!clocks.tick(Mark::Cross, 5_000));
+        clocks.moved(Mark::Cross);
+        assert_eq!(clocks.remaining(Mark::Cross), 57_000);
+        assert_eq!(clocks.remaining(Mark::Circle), 60_000);
+    }
+
+    #[test]
+    fn resets_the_time_per_move() {
+        let mut clocks = clocks(ClockMode::PerMove);
+        assert_eq!(clocks.remaining(Mark::Circle), 10_000);
+
+        assert!(!clocks.tick(Mark::Circle, 7_500));
+        assert_eq!(clocks.remaining(Mark::Circle), 2_500);
+        clocks.moved(Mark::Circle);
+        assert_eq!(clocks.remaining(Mark::Circle), 10_000);
+    }
+
+    #[test]
+    fn stops_while_paused() {
+        let mut clocks = clocks(ClockMode::Increment);
+        clocks.paused = true;
+        assert!(!clocks.tick(Mark::Cross, 120_000));
+        assert_eq!(clocks.remaining(Mark::Cross), 60_000);
+
+        clocks.paused = false;
+        assert!(!clocks.tick(Mark::Cross, 1_000));
+        assert_eq!(clocks.remaining(Mark::Cross), 59_000);
+    }
+
+    #[test]
+    fn falls_when_the_time_runs_out() {
+        let mut clocks = clocks(ClockMode::PerMove);
+        assert!(!clocks.tick(Mark::Cross, 9_999));
+        assert!(clocks.tick(Mark::Cross, 5_000));
+        assert_eq!(clocks.remaining(Mark::Cross), 0);
+    }
+
+    #[test]
+    fn runs_without_clocks_when_off() {
+        assert!(TimeControl::default().start().is_none());
+    }
+}
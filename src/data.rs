@@ -1,7 +1,18 @@
-use druid::{Data, Lens, ExtEventSink};
+use druid::{Data, Lens, ExtEventSink, Size};
 use std::ops::{Index, Deref};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
 use crate::data::Mark::{Cross, Circle};
-use crate::ai::best_move;
+use crate::ai::{best_move, analyse, ponder, stop_pondering, accepts_draw, new_search, is_current_search, EngineSettings};
+use crate::external::{request_move, default_command};
+use crate::network::{send_move, send_action, disconnect};
+use crate::network::protocol::{Action, DEFAULT_PORT};
+use crate::ai::solver::Solution;
+use crate::clock::{TimeControl, Clocks, ClockMode};
+use crate::series::Series;
+use crate::theme::Theme;
+use itertools::Itertools;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
 pub enum Mark {
@@ -18,6 +29,15 @@ impl Mark {
     }
 }
 
+impl Display for Mark {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Mark::Cross => write!(f, "Cross"),
+            Mark::Circle => write!(f, "Circle"),
+        }
+    }
+}
+
 impl Slot for Option<Mark> {
     fn belongs_to(&self) -> Option<Mark> {
         *self
@@ -43,6 +63,8 @@ pub struct Grid<S: Slot + Clone + Eq> {
     slots: [S; 9],
     finished: Option<Mark>,
     has_free: bool,
+    /// The grid can still be played in after it was won, see `Rules::OpenFields`.
+    open: bool,
 }
 
 pub type Field = Grid<Option<Mark>>;
@@ -61,7 +83,14 @@ impl From<(usize, usize)> for FieldPosition {
 }
 
 impl FieldPosition {
-    fn index(self) -> usize {
+    pub fn from_index(index: usize) -> Option<Self> {
+        if index < 9 {
+            Some(Self(index))
+        } else {
+            None
+        }
+    }
+    pub fn index(self) -> usize {
         self.0
     }
     pub fn x(self) -> usize {
@@ -73,10 +102,264 @@ impl FieldPosition {
     pub fn all() -> impl Iterator<Item=FieldPosition> + Clone {
         (0..9).map(|x|Self(x))
     }
+    pub fn center() -> Self {
+        Self(4)
+    }
+    /// The position in words, like `"top left"`.
+    pub fn name(self) -> &'static str {
+        [
+            "top left", "top", "top right",
+            "left", "center", "right",
+            "bottom left", "bottom", "bottom right",
+        ][self.0]
+    }
+}
+
+/// Positions are written as the digits 1 to 9, counting row by row from the top left.
+impl Display for FieldPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+impl FromStr for FieldPosition {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(value) if value >= 1 => Self::from_index(value - 1).ok_or(()),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A move is the small field to play in and the slot inside of it.
+pub type Move = (FieldPosition, FieldPosition);
+
+/// Writes a move as two digits, the small field followed by the slot, e.g. `"59"`.
+pub fn format_move((outer, inner): Move) -> String {
+    format!("{}{}", outer, inner)
+}
+
+pub fn parse_move(text: &str) -> Option<Move> {
+    let mut chars = text.trim().chars();
+    let outer = chars.next()?.to_string().parse().ok()?;
+    let inner = chars.next()?.to_string().parse().ok()?;
+
+    if chars.next().is_none() {
+        Some((outer, inner))
+    } else {
+        None
+    }
 }
 
 pub type LargeField = Grid<Field>;
 
+/// The variants of the rules a game can be played with.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum Rules {
+    /// A won small field is closed, like a full one.
+    Standard,
+    /// A won small field keeps its owner but can be played in until it is full.
+    OpenFields,
+}
+
+impl LargeField {
+    /// The empty board of a game played with `rules`.
+    pub fn new(rules: Rules) -> Self {
+        let mut game = LargeField::empty();
+        for field in &mut game.slots {
+            field.open = rules == Rules::OpenFields;
+        }
+        game
+    }
+
+    pub fn rules(&self) -> Rules {
+        if self.slots[0].open {Rules::OpenFields} else {Rules::Standard}
+    }
+
+    /// Places `mark` and returns the small field the next player has to play in.
+    pub fn play(&mut self, (outer, inner): Move, mark: Mark) -> Option<FieldPosition> {
+        let mut field = self[outer];
+        field.set(inner, Some(mark));
+        self.set(outer, field);
+
+        if self[inner].has_free() {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    pub fn legal_moves(&self, next_field: Option<FieldPosition>) -> impl Iterator<Item=Move> + '_ {
+        FieldPosition::all()
+            .filter(move |&pos|{
+                (next_field.is_none() || next_field == Some(pos)) && self[pos].has_free()
+            })
+            .cartesian_product(FieldPosition::all())
+            .filter(move |(outer, inner)|self[*outer][*inner].has_free())
+    }
+
+    pub fn is_legal(&self, next_field: Option<FieldPosition>, mv: Move) -> bool {
+        self.belongs_to().is_none() && self.legal_moves(next_field).any(|legal|legal == mv)
+    }
+
+    /// A stable hash of the position, used as key of the opening book.
+    pub fn position_key(&self, next_turn: Mark, next_field: Option<FieldPosition>) -> u64 {
+        const PRIME: u64 = 0x100000001b3;
+
+        let slots = FieldPosition::all()
+            .cartesian_product(FieldPosition::all())
+            .map(|(outer, inner)|match self[outer][inner] {
+                None => 0,
+                Some(Mark::Cross) => 1,
+                Some(Mark::Circle) => 2,
+            });
+        let turn = match next_turn {
+            Mark::Cross => 1,
+            Mark::Circle => 2,
+        };
+        let next = next_field.map_or(0, |pos|pos.index() as u64 + 1);
+        // The keys of the standard rules stay the same, the saved books still fit.
+        let rules = match self.rules() {
+            Rules::Standard => None,
+            Rules::OpenFields => Some(3),
+        };
+
+        slots.chain(Some(turn)).chain(Some(next)).chain(rules)
+            .fold(0xcbf29ce484222325, |hash, value|(hash ^ value).wrapping_mul(PRIME))
+    }
+}
+
+/// The state of a game without anything belonging to the user interface.
+///
+/// Written as the nine small fields separated by `/`, each as nine of `x`, `o` or `.`,
+/// followed by the mark to move and the field to play in or `-`. After Cross played
+/// the center of the center field:
+/// `........./........./........./........./....x..../........./........./........./......... o 5`
+/// The text is read with the standard rules.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Position {
+    pub game: LargeField,
+    pub next_turn: Mark,
+    pub next_field: Option<FieldPosition>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct IllegalMove(pub Move);
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal move {}", format_move(self.0))
+    }
+}
+
+impl Position {
+    pub fn start() -> Self {
+        Position {
+            game: LargeField::empty(),
+            next_turn: Mark::Cross,
+            next_field: None,
+        }
+    }
+
+    pub fn legal_moves(&self) -> impl Iterator<Item=Move> + '_ {
+        self.game.legal_moves(self.next_field)
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.game.is_legal(self.next_field, mv)
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<(), IllegalMove> {
+        if !self.is_legal(mv) {
+            return Err(IllegalMove(mv));
+        }
+        self.next_field = self.game.play(mv, self.next_turn);
+        self.next_turn = self.next_turn.other();
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.game.has_free()
+    }
+
+    pub fn key(&self) -> u64 {
+        self.game.position_key(self.next_turn, self.next_field)
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for outer in FieldPosition::all() {
+            if outer.index() > 0 {
+                write!(f, "/")?;
+            }
+            for inner in FieldPosition::all() {
+                write!(f, "{}", mark_char(self.game[outer][inner]))?;
+            }
+        }
+        let turn = match self.next_turn {
+            Mark::Cross => 'x',
+            Mark::Circle => 'o',
+        };
+        match self.next_field {
+            Some(pos) => write!(f, " {} {}", turn, pos),
+            None => write!(f, " {} -", turn),
+        }
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let fields = parts.next().ok_or("missing fields")?;
+        let turn = parts.next().ok_or("missing mark to move")?;
+        let next = parts.next().ok_or("missing next field")?;
+
+        let mut game = LargeField::empty();
+        let fields: Vec<_> = fields.split('/').collect();
+        if fields.len() != 9 {
+            return Err(String::from("expected nine fields"));
+        }
+        for (outer, text) in FieldPosition::all().zip(fields) {
+            let slots: Vec<_> = text.chars().collect();
+            if slots.len() != 9 {
+                return Err(format!("expected nine slots in field {}", outer));
+            }
+            let mut field = Field::empty();
+            for (inner, c) in FieldPosition::all().zip(slots) {
+                let mark = match c {
+                    'x' => Some(Mark::Cross),
+                    'o' => Some(Mark::Circle),
+                    '.' => None,
+                    _ => return Err(format!("unexpected '{}'", c)),
+                };
+                field.set(inner, mark);
+            }
+            game.set(outer, field);
+        }
+
+        let next_turn = match turn {
+            "x" => Mark::Cross,
+            "o" => Mark::Circle,
+            _ => return Err(format!("unexpected mark '{}'", turn)),
+        };
+        let next_field = match next {
+            "-" => None,
+            _ => Some(next.parse().map_err(|_|format!("unexpected field '{}'", next))?),
+        };
+        // `LargeField::play` never sends the next move to a won or full field.
+        if let Some(field) = next_field.filter(|&field|!game[field].has_free()) {
+            return Err(format!("field {} has no free slot", field));
+        }
+
+        Ok(Position {game, next_turn, next_field})
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct LargeFieldPosition(usize);
 
@@ -112,7 +395,10 @@ impl<S: Slot + Eq + Clone> Grid<S> {
             .or(self.column_finished(0))
             .or(self.column_finished(1))
             .or(self.column_finished(2));
-        self.finished = finished;
+        // An open grid keeps its first winner.
+        if !(self.open && self.finished.is_some()) {
+            self.finished = finished;
+        }
         let has_free = self.slots.iter().any(|slot|slot.has_free());
         self.has_free = has_free;
     }
@@ -121,6 +407,20 @@ impl<S: Slot + Eq + Clone> Grid<S> {
         self.slots[pos.into().index()] = mark;
         self.calc_finished();
     }
+
+    /// The row, column or diagonal which won the grid, from one end to the other.
+    pub fn winning_line(&self) -> Option<[FieldPosition; 3]> {
+        const LINES: [[usize; 3]; 8] = [
+            [0, 4, 8], [2, 4, 6],
+            [0, 1, 2], [3, 4, 5], [6, 7, 8],
+            [0, 3, 6], [1, 4, 7], [2, 5, 8],
+        ];
+        let winner = self.belongs_to()?;
+
+        LINES.iter()
+            .find(|line|line.iter().all(|index|self.slots[*index].belongs_to() == Some(winner)))
+            .map(|line|[FieldPosition(line[0]), FieldPosition(line[1]), FieldPosition(line[2])])
+    }
 }
 
 impl<S: Slot + Clone + Eq> Slot for Grid<S> {
@@ -129,7 +429,7 @@ impl<S: Slot + Clone + Eq> Slot for Grid<S> {
     }
 
     fn has_free(&self) -> bool {
-        self.has_free && self.belongs_to().is_none()
+        self.has_free && (self.open || self.belongs_to().is_none())
     }
 
     fn empty() -> Self {
@@ -141,6 +441,7 @@ impl<S: Slot + Clone + Eq> Slot for Grid<S> {
             ],
             finished: None,
             has_free: true,
+            open: false,
         }
     }
 }
@@ -153,37 +454,113 @@ impl<S: Slot + Clone + Eq, P: Into<FieldPosition>> Index<P> for Grid<S> {
     }
 }
 
+fn mark_char(mark: Option<Mark>) -> char {
+    match mark {
+        Some(Mark::Cross) => 'x',
+        Some(Mark::Circle) => 'o',
+        None => '.',
+    }
+}
+
+/// Three rows of `x`, `o` or `.`, separated by spaces.
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for y in 0..3 {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} {} {}", mark_char(self[(0, y)]), mark_char(self[(1, y)]), mark_char(self[(2, y)]))?;
+        }
+        Ok(())
+    }
+}
+
+/// The 9×9 slots with lines between the small fields, see `LargeField::display`.
+impl Display for LargeField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.display(None).fmt(f)
+    }
+}
+
+impl LargeField {
+    /// Shows the board, the free slots of `next_field` as `*`.
+    /// All slots of a won small field show its winner as `X` or `O`, those of a full
+    /// one without a winner `#`.
+    pub fn display(&self, next_field: Option<FieldPosition>) -> BoardDisplay<'_> {
+        BoardDisplay {field: self, next_field}
+    }
+}
+
+pub struct BoardDisplay<'a> {
+    field: &'a LargeField,
+    next_field: Option<FieldPosition>,
+}
+
+impl Display for BoardDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for y in 0..9 {
+            if y > 0 && y % 3 == 0 {
+                writeln!(f, "------+-------+------")?;
+            }
+            for x in 0..9 {
+                let outer = FieldPosition::from((x / 3, y / 3));
+                let field = &self.field[outer];
+
+                let c = match field.belongs_to() {
+                    Some(mark) => mark_char(Some(mark)).to_ascii_uppercase(),
+                    None if !field.has_free() => '#',
+                    None if self.next_field == Some(outer) && field[(x % 3, y % 3)].is_none() => '*',
+                    None => mark_char(field[(x % 3, y % 3)]),
+                };
+                match x {
+                    0 => write!(f, "{}", c)?,
+                    3 | 6 => write!(f, " | {}", c)?,
+                    _ => write!(f, " {}", c)?,
+                }
+            }
+            if y < 8 {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Data)]
 pub struct FieldMeta {
     field: Field,
     next_turn: Mark,
     written: Option<FieldPosition>,
     active: bool,
+    /// The slot of the keyboard cursor, if it is in this field.
+    cursor: Option<FieldPosition>,
+    /// The slot of the last move, if it was in this field.
+    last_move: Option<FieldPosition>,
+    position: FieldPosition,
+    /// The line of small fields which won the game, if this field is part of it.
+    board_line: Option<[FieldPosition; 3]>,
+    animate: bool,
 }
 
 impl FieldMeta {
     pub fn from_data(game_data: &GameData, position: impl Into<FieldPosition> + Clone) -> Self {
+        let position = position.into();
+
         FieldMeta {
-            field: game_data.game[position.clone()].clone(),
+            field: game_data.game[position],
             next_turn: game_data.next_turn,
-            active: (game_data.next_field == Some(position.clone().into()) || game_data.next_field == None) &&
-                game_data.game[position.into()].has_free() &&
-                game_data.game.belongs_to().is_none() &&
-                game_data.my_turn(),
+            active: game_data.is_playable(position),
             written: None,
+            cursor: game_data.cursor.filter(|(outer, _)|*outer == position).map(|(_, inner)|inner),
+            last_move: game_data.last_move.filter(|(outer, _)|*outer == position).map(|(_, inner)|inner),
+            position,
+            board_line: game_data.game.winning_line().filter(|line|line.contains(&position)),
+            animate: game_data.animations,
         }
     }
-    pub fn write_back(mut self, game_data: &mut GameData, field_position: impl Into<FieldPosition>) {
+    pub fn write_back(self, game_data: &mut GameData, field_position: impl Into<FieldPosition>) {
         if let Some(position) = self.written {
-            self.field.set(position, Some(self.next_turn));
-            game_data.game.set(field_position, self.field);
-
-            game_data.next_turn = game_data.next_turn.other();
-            game_data.next_field = if game_data.game[position].has_free() {
-                Some(position)
-            } else {
-                None
-            };
+            game_data.play((field_position.into(), position));
         }
     }
     pub fn is_active(&self) -> bool {
@@ -197,6 +574,21 @@ impl FieldMeta {
     pub fn next_turn(&self) -> Mark {
         self.next_turn
     }
+    pub fn cursor(&self) -> Option<FieldPosition> {
+        self.cursor
+    }
+    pub fn last_move(&self) -> Option<FieldPosition> {
+        self.last_move
+    }
+    pub fn position(&self) -> FieldPosition {
+        self.position
+    }
+    pub fn board_line(&self) -> Option<[FieldPosition; 3]> {
+        self.board_line
+    }
+    pub fn animate(&self) -> bool {
+        self.animate
+    }
 }
 
 impl Deref for FieldMeta {
@@ -207,47 +599,724 @@ impl Deref for FieldMeta {
     }
 }
 
-#[derive(Copy, Clone, Data)]
+#[derive(Clone, Data)]
 pub enum Opponent {
     Ai {level: u64},
+    /// A program speaking the engine protocol, started by `command`.
+    External {command: String},
+    /// A player on another computer, connected over the network.
+    Remote,
+}
+
+/// Who is played against when the app starts.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum StartOpponent {
+    TwoPlayers,
+    /// The AI playing `ai_mark`.
+    Ai,
+    /// The external engine playing `ai_mark`.
+    Engine,
+}
+
+/// How a game ended before the board was decided.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum Ending {
+    /// The mark ran out of time.
+    Timeout(Mark),
+    /// The mark gave up.
+    Resigned(Mark),
+    DrawAgreed,
 }
 
+pub const DEFAULT_LEVEL: u64 = 4;
+
+/// The minimal time between two moves of the AI.
+pub const DEFAULT_AI_DELAY: u64 = 800;
+
+pub const DEFAULT_SERIES_LENGTH: u64 = 3;
+
 #[derive(Clone, Data, Lens)]
 pub struct GameData {
     pub game: LargeField,
     pub next_turn: Mark,
     pub next_field: Option<FieldPosition>,
-    pub opponent: Option<(Opponent, Mark)>,
+    pub last_move: Option<Move>,
+    /// The last move came over the network, so it must not be sent back.
+    pub last_move_remote: bool,
+    /// Who plays the crosses, `None` if it is a player on this computer.
+    pub cross: Option<Opponent>,
+    /// Who plays the circles, `None` if it is a player on this computer.
+    pub circle: Option<Opponent>,
+    pub analysis: Option<Solution>,
+    /// The difficulty for the next game, per mark.
+    pub cross_level: u64,
+    pub circle_level: u64,
+    /// The mark the AI plays in the next game against it.
+    pub ai_mark: Mark,
+    /// The minimal time between two moves of the AI in milliseconds.
+    pub ai_delay: u64,
+    /// Lets the AI think while the player is deciding.
+    pub ponder: bool,
+    /// Starts the external engine for the next game against it.
+    pub engine_command: String,
+    /// Tells the player about problems, like a crashed engine.
+    pub message: Option<String>,
+    /// The address to host or join network games.
+    pub address: String,
+    /// The time control for the next games.
+    pub time_control: TimeControl,
+    /// The rules of the next games played on this computer.
+    pub rules: Rules,
+    pub clocks: Option<Clocks>,
+    pub ending: Option<Ending>,
+    /// The mark which offered a draw, until the opponent moves.
+    pub draw_offer: Option<Mark>,
+    /// The mark which asked for a rematch in a network game.
+    pub rematch_offer: Option<Mark>,
+    /// The number of games of the next series.
+    pub series_length: u64,
+    pub series: Option<Series>,
+    /// The slot selected with the keyboard, `None` while the keyboard is not used.
+    pub cursor: Option<Move>,
+    pub theme: Theme,
+    /// Animates new marks, won fields and the highlight of the fields to play in.
+    pub animations: bool,
+    /// Who is played against when the app starts.
+    pub start_opponent: StartOpponent,
+    /// Shows the settings instead of the board.
+    pub show_settings: bool,
+    /// The size of the window, kept to open it as large the next time.
+    pub window_size: Size,
 }
 
 impl GameData {
-    pub fn local() -> Self {
+    /// A game between two players on this computer, in a window of `window_size`.
+    pub fn local(window_size: Size) -> Self {
         Self {
             game: LargeField::empty(),
             next_turn: Mark::Cross,
             next_field: None,
-            opponent: None,
+            last_move: None,
+            last_move_remote: false,
+            cross: None,
+            circle: None,
+            analysis: None,
+            cross_level: DEFAULT_LEVEL,
+            circle_level: DEFAULT_LEVEL,
+            ai_mark: Mark::Circle,
+            ai_delay: DEFAULT_AI_DELAY,
+            ponder: false,
+            engine_command: default_command(),
+            message: None,
+            address: format!("127.0.0.1:{}", DEFAULT_PORT),
+            time_control: TimeControl::default(),
+            rules: Rules::Standard,
+            clocks: None,
+            ending: None,
+            draw_offer: None,
+            rematch_offer: None,
+            series_length: DEFAULT_SERIES_LENGTH,
+            series: None,
+            cursor: None,
+            theme: Theme::Classic,
+            animations: true,
+            start_opponent: StartOpponent::TwoPlayers,
+            show_settings: false,
+            window_size,
         }
     }
 
-    pub fn ai(level: u64) -> Self {
-        Self {
-            game: LargeField::empty(),
-            next_turn: Mark::Cross,
-            next_field: None,
-            opponent: Some((Opponent::Ai {level}, Mark::Circle)),
+    /// Starts the first game against `start_opponent`.
+    pub fn start(&mut self) {
+        match self.start_opponent {
+            StartOpponent::TwoPlayers => self.restart(None, None),
+            StartOpponent::Ai => self.restart_ai(),
+            StartOpponent::Engine => self.restart_external(),
+        }
+    }
+
+    /// Starts a new game, keeping the settings for the next games. A running series ends.
+    pub fn restart(&mut self, cross: Option<Opponent>, circle: Option<Opponent>) {
+        self.cross = cross;
+        self.circle = circle;
+        self.game = LargeField::new(self.game_rules());
+        self.next_turn = Mark::Cross;
+        self.next_field = None;
+        self.last_move = None;
+        self.last_move_remote = false;
+        self.analysis = None;
+        self.message = None;
+        // The peers can't agree on the time, network games are played without clocks.
+        self.clocks = if self.has_remote() {None} else {self.time_control.start()};
+        self.ending = None;
+        self.draw_offer = None;
+        self.rematch_offer = None;
+        self.series = None;
+    }
+
+    /// Starts a series of `series_length` games between the players of the current game.
+    pub fn start_series(&mut self) {
+        if self.has_remote() {
+            self.message = Some(String::from("Series are played on this computer only"));
+            return;
+        }
+        let series = Series::new(self.series_length, self.cross.clone(), self.circle.clone());
+        self.restart(self.cross.clone(), self.circle.clone());
+        self.series = Some(series);
+    }
+
+    /// Counts the finished game for the running series.
+    pub fn record_result(&mut self) {
+        if !self.is_over() {
+            return;
+        }
+        let winner = self.winner();
+        if let Some(series) = &mut self.series {
+            series.record(winner);
+        }
+    }
+
+    /// Starts the next game of the series, the players swap their marks.
+    pub fn next_game(&mut self) {
+        let mut series = match self.series.take() {
+            Some(series) => series,
+            None => return,
+        };
+        if series.next_game() {
+            let (cross, circle) = series.players();
+            self.restart(cross, circle);
         }
+        self.series = Some(series);
+    }
+
+    /// Starts a new game against `opponent` playing `ai_mark`.
+    pub fn restart_against(&mut self, opponent: Opponent) {
+        match self.ai_mark {
+            Mark::Cross => self.restart(Some(opponent), None),
+            Mark::Circle => self.restart(None, Some(opponent)),
+        }
+    }
+
+    /// Starts a new game against the AI playing `ai_mark`.
+    pub fn restart_ai(&mut self) {
+        self.restart_against(Opponent::Ai {level: self.level(self.ai_mark)});
+    }
+
+    /// Starts a new game against the external engine playing `ai_mark`.
+    pub fn restart_external(&mut self) {
+        self.restart_against(Opponent::External {command: self.engine_command.clone()});
+    }
+
+    /// Starts a new game where the AI plays both sides.
+    pub fn restart_ai_vs_ai(&mut self) {
+        self.restart(
+            Some(Opponent::Ai {level: self.cross_level}),
+            Some(Opponent::Ai {level: self.circle_level}),
+        );
+    }
+
+    pub fn level(&self, mark: Mark) -> u64 {
+        match mark {
+            Mark::Cross => self.cross_level,
+            Mark::Circle => self.circle_level,
+        }
+    }
+
+    pub fn set_level(&mut self, mark: Mark, level: u64) {
+        match mark {
+            Mark::Cross => self.cross_level = level,
+            Mark::Circle => self.circle_level = level,
+        }
+    }
+
+    pub fn opponent(&self, mark: Mark) -> Option<&Opponent> {
+        match mark {
+            Mark::Cross => self.cross.as_ref(),
+            Mark::Circle => self.circle.as_ref(),
+        }
+    }
+
+    pub fn has_remote(&self) -> bool {
+        matches!(self.cross, Some(Opponent::Remote)) || matches!(self.circle, Some(Opponent::Remote))
+    }
+
+    /// The rules of a new game, peers and external engines only know the standard rules.
+    fn game_rules(&self) -> Rules {
+        let external = matches!(self.cross, Some(Opponent::External {..})) ||
+            matches!(self.circle, Some(Opponent::External {..}));
+        if self.has_remote() || external {Rules::Standard} else {self.rules}
     }
 
     pub fn handle_opponent(&self, sink: ExtEventSink) {
-        if let Some((Opponent::Ai {level}, mark)) = self.opponent.as_ref() {
-            if *mark == self.next_turn {
-                best_move(self.game.clone(), *mark, self.next_field, *level, sink);
+        // Whatever was searched for the previous state of the game is outdated.
+        let search = new_search();
+
+        if !self.has_remote() {
+            disconnect();
+        }
+        if self.is_over() || self.is_paused() {
+            stop_pondering();
+            return;
+        }
+        match (self.opponent(self.next_turn), self.opponent(self.next_turn.other())) {
+            (Some(Opponent::Ai {level}), _) => {
+                let mut settings = EngineSettings::preset(*level);
+                let mut delay = self.ai_delay;
+                if let Some(clocks) = self.clocks {
+                    let budget = clocks.budget(self.next_turn);
+                    settings.time_limit = Some(settings.time_limit.map_or(budget, |limit|limit.min(budget)));
+                    delay = delay.min(budget / 2);
+                }
+                best_move(self.game, self.next_turn, self.next_field, settings, Duration::from_millis(delay), search, sink);
+            }
+            (Some(Opponent::External {command}), _) => {
+                stop_pondering();
+                request_move(command.clone(), self.position(), search, sink);
+            }
+            (Some(Opponent::Remote), _) => {
+                stop_pondering();
+                match self.last_move {
+                    Some(mv) if !self.last_move_remote => send_move(mv),
+                    _ => {}
+                }
+            }
+            (None, Some(Opponent::Ai {level})) if self.ponder => {
+                ponder(self.game, self.next_turn, self.next_field, EngineSettings::preset(*level));
+            }
+            _ => stop_pondering(),
+        }
+    }
+
+    /// The external engine failed in the search `search`, the AI continues the game in its
+    /// place. Failures of outdated searches are ignored.
+    pub fn engine_failed(&mut self, search: u64, error: &str) {
+        if !is_current_search(search) {
+            return;
+        }
+        if let Some(Opponent::External {..}) = self.opponent(self.next_turn) {
+            let ai = Some(Opponent::Ai {level: self.level(self.next_turn)});
+            match self.next_turn {
+                Mark::Cross => self.cross = ai,
+                Mark::Circle => self.circle = ai,
+            }
+            self.message = Some(format!("{}, the AI takes over", error));
+        }
+    }
+
+    /// A peer connected, a new game against it starts.
+    pub fn connected(&mut self, peer: Mark) {
+        match peer {
+            Mark::Cross => self.restart(Some(Opponent::Remote), None),
+            Mark::Circle => self.restart(None, Some(Opponent::Remote)),
+        }
+        if self.time_control.mode != ClockMode::Off {
+            self.message = Some(String::from("Network games are played without clocks"));
+        }
+    }
+
+    /// The mark played on this computer, the one to move if both are.
+    /// `None` if no player is on this computer.
+    pub fn local_mark(&self) -> Option<Mark> {
+        if self.my_turn() {
+            Some(self.next_turn)
+        } else if self.opponent(self.next_turn.other()).is_none() {
+            Some(self.next_turn.other())
+        } else {
+            None
+        }
+    }
+
+    pub fn resign(&mut self) {
+        let mark = match self.local_mark() {
+            Some(mark) if !self.is_over() => mark,
+            _ => return,
+        };
+        self.ending = Some(Ending::Resigned(mark));
+        if let Some(Opponent::Remote) = self.opponent(mark.other()) {
+            send_action(Action::Resign, mark);
+        }
+    }
+
+    /// Offers a draw to the opponent, or accepts its offer.
+    pub fn offer_draw(&mut self) {
+        let mark = match (self.local_mark(), self.draw_offer) {
+            (None, _) => return,
+            _ if self.is_over() => return,
+            // Two players on this computer share the button, the second press accepts.
+            (Some(_), Some(offer)) if self.opponent(offer.other()).is_none() => offer.other(),
+            (Some(mark), _) => mark,
+        };
+
+        match self.opponent(mark.other()) {
+            Some(Opponent::Remote) => {
+                send_action(Action::OfferDraw, mark);
+                if self.draw_offer == Some(mark.other()) {
+                    self.ending = Some(Ending::DrawAgreed);
+                } else {
+                    self.draw_offer = Some(mark);
+                    self.message = Some(String::from("You offered a draw"));
+                }
+            }
+            Some(_) => {
+                if accepts_draw(self.game, mark.other(), self.next_turn, self.next_field) {
+                    self.ending = Some(Ending::DrawAgreed);
+                } else {
+                    self.message = Some(String::from("The AI declines the draw"));
+                }
+            }
+            None if self.draw_offer == Some(mark.other()) => self.ending = Some(Ending::DrawAgreed),
+            None => {
+                self.draw_offer = Some(mark);
+                self.message = Some(format!("{} offers a draw", mark));
+            }
+        }
+    }
+
+    /// Starts a new game with swapped marks. In network games the peer has to agree first.
+    pub fn rematch(&mut self) {
+        if !self.has_remote() {
+            self.swap_sides();
+            return;
+        }
+        let mark = match self.local_mark() {
+            Some(mark) if self.is_over() => mark,
+            _ => return,
+        };
+
+        send_action(Action::Rematch, mark);
+        if self.rematch_offer == Some(mark.other()) {
+            self.swap_sides();
+        } else {
+            self.rematch_offer = Some(mark);
+            self.message = Some(String::from("You asked for a rematch"));
+        }
+    }
+
+    fn swap_sides(&mut self) {
+        self.restart(self.circle.clone(), self.cross.clone());
+    }
+
+    /// The player of `mark` on another computer did something besides moving.
+    pub fn remote_action(&mut self, action: Action, mark: Mark) -> Result<(), String> {
+        if !matches!(self.opponent(mark), Some(Opponent::Remote)) {
+            return Err(String::from("the opponent acted for the wrong side"));
+        }
+
+        match action {
+            Action::Resign if !self.is_over() => self.ending = Some(Ending::Resigned(mark)),
+            Action::OfferDraw if !self.is_over() => {
+                if self.draw_offer == Some(mark.other()) {
+                    self.ending = Some(Ending::DrawAgreed);
+                } else {
+                    self.draw_offer = Some(mark);
+                    self.message = Some(format!("{} offers a draw", mark));
+                }
+            }
+            Action::Rematch => {
+                if self.rematch_offer == Some(mark.other()) {
+                    self.swap_sides();
+                } else {
+                    self.rematch_offer = Some(mark);
+                    self.message = Some(format!("{} asks for a rematch", mark));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Watching a game on a server started, both sides are played remotely.
+    pub fn watching(&mut self, moves: &[Move]) {
+        self.restart(Some(Opponent::Remote), Some(Opponent::Remote));
+        self.replay(moves);
+        self.message = Some(String::from("Watching the game"));
+    }
+
+    /// The connection was lost and resumed, the game continues with the moves the server knows.
+    /// Only the board is rebuilt, the offers and the ending of the match are kept.
+    pub fn resumed(&mut self, peer: Mark, moves: &[Move]) {
+        match peer {
+            Mark::Cross => {
+                self.cross = Some(Opponent::Remote);
+                self.circle = None;
+            }
+            Mark::Circle => {
+                self.cross = None;
+                self.circle = Some(Opponent::Remote);
+            }
+        }
+        self.replay(moves);
+        self.message = Some(String::from("Reconnected to the game"));
+    }
+
+    /// Rebuilds the board from the moves of a game received over the network.
+    fn replay(&mut self, moves: &[Move]) {
+        let mut position = Position::start();
+        let mut last_move = None;
+        for mv in moves {
+            if position.play(*mv).is_err() {
+                break;
             }
+            last_move = Some(*mv);
+        }
+        self.game = position.game;
+        self.next_turn = position.next_turn;
+        self.next_field = position.next_field;
+        self.last_move = last_move;
+        self.last_move_remote = true;
+        self.analysis = None;
+    }
+
+    /// Plays the move of the peer, if it is its turn and the move is legal.
+    pub fn remote_move(&mut self, mv: Move) -> Result<(), String> {
+        if self.ending.is_some() {
+            // The peer may not have noticed yet that the game is over.
+            return Ok(());
+        }
+        match self.opponent(self.next_turn) {
+            Some(Opponent::Remote) if self.game.is_legal(self.next_field, mv) => {
+                self.play(mv);
+                self.last_move_remote = true;
+                Ok(())
+            }
+            Some(Opponent::Remote) => Err(format!("the opponent sent the illegal move {}", format_move(mv))),
+            _ => Err(String::from("the opponent moved out of turn")),
+        }
+    }
+
+    /// The connection to the peer ended, the game continues locally.
+    pub fn disconnected(&mut self, reason: &str) {
+        if let Some(Opponent::Remote) = self.cross {
+            self.cross = None;
+        }
+        if let Some(Opponent::Remote) = self.circle {
+            self.circle = None;
+        }
+        self.message = Some(reason.to_string());
+    }
+
+    pub fn play(&mut self, mv: Move) {
+        self.last_move = Some(mv);
+        self.last_move_remote = false;
+        if let Some(clocks) = &mut self.clocks {
+            clocks.moved(self.next_turn);
+        }
+        // Moving declines the draw offered by the opponent.
+        if self.draw_offer == Some(self.next_turn.other()) {
+            self.draw_offer = None;
+        }
+        self.next_field = self.game.play(mv, self.next_turn);
+        self.next_turn = self.next_turn.other();
+        self.analysis = None;
+        // The cursor follows to the field of the next move.
+        if let (Some((outer, inner)), Some(next)) = (self.cursor, self.next_field) {
+            if outer != next {
+                self.cursor = Some((next, inner));
+            }
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        Position {
+            game: self.game,
+            next_turn: self.next_turn,
+            next_field: self.next_field,
+        }
+    }
+
+    pub fn position_key(&self) -> u64 {
+        self.position().key()
+    }
+
+    pub fn handle_analysis(&self, sink: ExtEventSink) {
+        analyse(self.game, self.next_turn, self.next_field, sink);
+    }
+
+    /// The mark which won the game, on the board or otherwise.
+    pub fn winner(&self) -> Option<Mark> {
+        match self.ending {
+            Some(Ending::Timeout(mark)) | Some(Ending::Resigned(mark)) => Some(mark.other()),
+            Some(Ending::DrawAgreed) => None,
+            None => self.game.belongs_to(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.ending.is_some() || !self.game.has_free()
+    }
+
+    /// Counts down the clock of the mark to move, it loses when its time runs out.
+    /// Only the clocks of players on this computer are run.
+    pub fn tick_clock(&mut self, elapsed: u64) {
+        if self.is_over() || matches!(self.opponent(self.next_turn), Some(Opponent::Remote)) {
+            return;
+        }
+        if let Some(clocks) = &mut self.clocks {
+            if clocks.tick(self.next_turn, elapsed) {
+                self.ending = Some(Ending::Timeout(self.next_turn));
+            }
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clocks.is_some_and(|clocks|clocks.paused)
+    }
+
+    /// Stops or continues the clocks, not possible in network games.
+    pub fn toggle_pause(&mut self) {
+        if self.has_remote() {
+            return;
+        }
+        if let Some(clocks) = &mut self.clocks {
+            clocks.paused = !clocks.paused;
         }
     }
 
     pub fn my_turn(&self) -> bool {
-        self.opponent.as_ref().map(|op|op.1) != Some(self.next_turn)
+        self.opponent(self.next_turn).is_none()
     }
-}
\ No newline at end of file
+
+    /// Whether the player on this computer may play in `field` now.
+    pub fn is_playable(&self, field: FieldPosition) -> bool {
+        self.next_field.is_none_or(|next|next == field) &&
+            self.game[field].has_free() &&
+            !self.is_over() &&
+            !self.is_paused() &&
+            self.my_turn()
+    }
+
+    /// Shows the keyboard cursor, in the field of the next move if there is one.
+    pub fn show_cursor(&mut self) {
+        if self.cursor.is_none() {
+            self.cursor = Some((self.next_field.unwrap_or_else(FieldPosition::center), FieldPosition::center()));
+        }
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    /// Moves the keyboard cursor by `dx` and `dy` slots, continuing on the other side
+    /// of the board at its edges.
+    pub fn move_cursor(&mut self, dx: isize, dy: isize) {
+        self.show_cursor();
+        if let Some((outer, inner)) = self.cursor {
+            let x = ((outer.x() * 3 + inner.x()) as isize + dx).rem_euclid(9) as usize;
+            let y = ((outer.y() * 3 + inner.y()) as isize + dy).rem_euclid(9) as usize;
+            self.cursor = Some((FieldPosition::from((x / 3, y / 3)), FieldPosition::from((x % 3, y % 3))));
+        }
+    }
+
+    /// Places the mark of the player under the keyboard cursor, if the move is possible.
+    pub fn play_cursor(&mut self) {
+        if let Some((outer, inner)) = self.cursor {
+            if self.is_playable(outer) && self.game[outer][inner].is_none() {
+                self.play((outer, inner));
+            }
+        }
+    }
+
+    /// The board in words, for players who can't see it.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+
+        if let Some((outer, inner)) = self.cursor {
+            let slot = match self.game[outer][inner] {
+                Some(mark) => format!("taken by {}", mark),
+                None if self.is_playable(outer) => String::from("free"),
+                None => String::from("free, not playable now"),
+            };
+            description.push_str(&format!("Cursor on the {} slot of the {} field, {}. ", inner.name(), outer.name(), slot));
+        }
+        for mark in [Mark::Cross, Mark::Circle].iter().copied() {
+            let won = FieldPosition::all()
+                .filter(|field|self.game[*field].belongs_to() == Some(mark))
+                .map(FieldPosition::name)
+                .join(", ");
+            if !won.is_empty() {
+                description.push_str(&format!("{} won {}. ", mark, won));
+            }
+        }
+        if !self.is_over() {
+            match self.next_field {
+                Some(field) => description.push_str(&format!("{} to move in the {} field.", self.next_turn, field.name())),
+                None => description.push_str(&format!("{} to move in any field.", self.next_turn)),
+            }
+        }
+        description.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_the_board() {
+        let position: Position = "xxx....../xoxxoooxx/........./........./....x..../........./........./........./......... o 5"
+            .parse().unwrap();
+
+        assert_eq!(position.game.display(position.next_field).to_string(), "\
+X X X | # # # | . . .
+X X X | # # # | . . .
+X X X | # # # | . . .
+------+-------+------
+. . . | * * * | . . .
+. . . | * x * | . . .
+. . . | * * * | . . .
+------+-------+------
+. . . | . . . | . . .
+. . . | . . . | . . .
+. . . | . . . | . . .");
+        assert_eq!(position.game.to_string().lines().nth(5), Some(". . . | . x . | . . ."));
+    }
+
+    #[test]
+    fn plays_in_won_fields_with_open_fields() {
+        let play = |game: &mut LargeField, moves: &[&str], mark|{
+            moves.iter().map(|mv|game.play(parse_move(mv).unwrap(), mark)).last().unwrap()
+        };
+        let first = FieldPosition::from_index(0).unwrap();
+
+        let mut standard = LargeField::new(Rules::Standard);
+        play(&mut standard, &["11", "12", "13"], Mark::Cross);
+        assert_eq!(play(&mut standard, &["21"], Mark::Circle), None);
+        assert!(!standard.is_legal(None, parse_move("14").unwrap()));
+
+        let mut open = LargeField::new(Rules::OpenFields);
+        play(&mut open, &["11", "12", "13"], Mark::Cross);
+        assert_eq!(open[first].belongs_to(), Some(Mark::Cross));
+        assert_eq!(play(&mut open, &["21"], Mark::Circle), Some(first));
+        assert!(open.is_legal(Some(first), parse_move("14").unwrap()));
+
+        // The field keeps its first winner.
+        play(&mut open, &["14", "15", "16"], Mark::Circle);
+        assert_eq!(open[first].belongs_to(), Some(Mark::Cross));
+        play(&mut open, &["17", "18", "19"], Mark::Circle);
+        assert!(!open[first].has_free());
+
+        // The book and the table don't mix up the rules.
+        let key = |rules|LargeField::new(rules).position_key(Mark::Cross, None);
+        assert_eq!(key(Rules::Standard), LargeField::empty().position_key(Mark::Cross, None));
+        assert_ne!(key(Rules::OpenFields), key(Rules::Standard));
+    }
+
+    #[test]
+    fn keeps_the_match_when_resumed() {
+        let mut data = GameData::local(Size::new(800.0, 600.0));
+        data.connected(Mark::Circle);
+        data.draw_offer = Some(Mark::Circle);
+        data.disconnected("Connection lost");
+
+        let moves: Vec<Move> = ["55", "51", "15"].iter().map(|mv|parse_move(mv).unwrap()).collect();
+        data.resumed(Mark::Circle, &moves);
+
+        assert_eq!(data.draw_offer, Some(Mark::Circle));
+        assert!(matches!(data.circle, Some(Opponent::Remote)));
+        assert!(data.cross.is_none());
+        assert_eq!(data.next_turn, Mark::Circle);
+        assert_eq!(data.last_move, Some(moves[2]));
+        assert_eq!(data.game.to_string().lines().nth(1), Some(". x . | . . . | . . ."));
+        assert_eq!(data.game.to_string().lines().nth(4), Some(". . . | o . . | . . ."));
+    }
+}
@@ -0,0 +1,260 @@
+//! A line based protocol to control the AI, similar to UCI for chess.
+//!
+//! Commands read from stdin:
+//! - `uti`: answered by the engine's `id` and `utiok`
+//! - `isready`: answered by `readyok`
+//! - `newgame`
+//! - `position (startpos | fen <notation>) [moves <move>...]`
+//! - `go [depth <plies>] [movetime <ms>] [level <level>] [infinite]`
+//! - `stop`: ends the search, which then reports its `bestmove`
+//! - `quit`
+//!
+//! While searching, the engine writes `info depth <plies> score <score> move <move>`
+//! after every finished depth and finally `bestmove <move>`, or `bestmove none` if
+//! the game is already over. Moves are written as two digits, see `format_move`.
+
+use crate::data::{Position, Move, format_move, parse_move};
+use crate::ai::{EngineSettings, SearchLimit, instant_move, score_moves_at, choose_move, stop_pondering};
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Searched when `go` has no `depth` or `level`.
+const DEFAULT_DEPTH: u64 = 6;
+
+/// Searched with `go infinite`, more plies than any game can have.
+const MAX_DEPTH: u64 = 81;
+
+pub fn run() {
+    let stdin = io::stdin();
+    let mut position = Position::start();
+    let mut search: Option<Search> = None;
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("uti") => {
+                send("id name Tick Tack Toe Ultimate");
+                send("id author xarvic");
+                send("utiok");
+            }
+            Some("isready") => send("readyok"),
+            Some("newgame") => {
+                wait(&mut search);
+                position = Position::start();
+            }
+            Some("position") => {
+                wait(&mut search);
+                match parse_position(words) {
+                    Ok(new_position) => position = new_position,
+                    Err(err) => send(&format!("info string {}", err)),
+                }
+            }
+            Some("go") => {
+                wait(&mut search);
+                let (settings, infinite) = parse_go(words);
+                let stop = Arc::new(AtomicBool::new(false));
+                let handle = go(position, settings, infinite, stop.clone());
+                search = Some(Search {stop, handle, infinite});
+            }
+            Some("stop") => finish(&mut search),
+            Some("quit") => break,
+            Some(command) => send(&format!("info string unknown command {}", command)),
+            None => {}
+        }
+    }
+
+    finish(&mut search);
+}
+
+fn send(line: &str) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    infinite: bool,
+}
+
+/// Stops a running search and waits for its `bestmove`.
+fn finish(search: &mut Option<Search>) {
+    if let Some(search) = search.take() {
+        search.stop.store(true, Ordering::Relaxed);
+        let _ = search.handle.join();
+    }
+}
+
+/// Waits until a running search reports its `bestmove`, an infinite search is stopped.
+fn wait(search: &mut Option<Search>) {
+    if let Some(search) = search.take() {
+        if search.infinite {
+            search.stop.store(true, Ordering::Relaxed);
+        }
+        let _ = search.handle.join();
+    }
+}
+
+fn parse_position<'a>(mut words: impl Iterator<Item=&'a str>) -> Result<Position, String> {
+    let mut position = match words.next() {
+        Some("startpos") => match words.next() {
+            None | Some("moves") => Position::start(),
+            Some(word) => return Err(format!("unexpected '{}' after startpos", word)),
+        },
+        Some("fen") => {
+            let notation: Vec<_> = words.by_ref().take_while(|word|*word != "moves").collect();
+            notation.join(" ").parse()?
+        }
+        setup => return Err(format!("unknown position '{}'", setup.unwrap_or(""))),
+    };
+
+    for text in words {
+        let mv = parse_move(text).ok_or_else(||format!("invalid move '{}'", text))?;
+        position.play(mv).map_err(|err|err.to_string())?;
+    }
+
+    Ok(position)
+}
+
+/// The settings of `go`, `depth` and `movetime` override those of the `level`,
+/// whatever their order.
+fn parse_go<'a>(mut words: impl Iterator<Item=&'a str>) -> (EngineSettings, bool) {
+    let (mut level, mut depth, mut time_limit) = (None, None, None);
+    let mut infinite = false;
+
+    while let Some(word) = words.next() {
+        match word {
+            "depth" => depth = words.next().and_then(|w|w.parse().ok()).or(depth),
+            "movetime" => time_limit = words.next().and_then(|w|w.parse().ok()).or(time_limit),
+            "level" => level = words.next().and_then(|w|w.parse().ok()).or(level),
+            "infinite" => infinite = true,
+            _ => {}
+        }
+    }
+
+    let mut settings = level.map_or(EngineSettings::exact(DEFAULT_DEPTH), EngineSettings::preset);
+    if let Some(depth) = depth {
+        settings.depth = depth;
+    }
+    if time_limit.is_some() {
+        settings.time_limit = time_limit;
+    }
+
+    if infinite {
+        settings.depth = MAX_DEPTH;
+        settings.time_limit = None;
+    }
+
+    (settings, infinite)
+}
+
+fn go(position: Position, settings: EngineSettings, infinite: bool, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+    stop_pondering();
+
+    spawn(move ||{
+        let best = search(position, &settings, infinite, stop);
+
+        match best {
+            Some(mv) => send(&format!("bestmove {}", format_move(mv))),
+            None => send("bestmove none"),
+        }
+    })
+}
+
+fn search(position: Position, settings: &EngineSettings, infinite: bool, stop: Arc<AtomicBool>) -> Option<Move> {
+    if position.is_finished() || position.game.legal_moves(position.next_field).next().is_none() {
+        return None;
+    }
+    if !infinite {
+        if let Some(mv) = instant_move(position.game, position.next_turn, position.next_field, settings) {
+            send("info string book or solved");
+            return Some(mv);
+        }
+    }
+
+    let limit = SearchLimit {
+        deadline: settings.time_limit.map(|limit|Instant::now() + Duration::from_millis(limit)),
+        stop: Some(stop),
+    };
+    let mut best = None;
+
+    for depth in 1..=settings.depth.max(1) {
+        // The first depth always has to finish, so there is a move to report.
+        let limit = if depth == 1 { SearchLimit::default() } else { limit.clone() };
+
+        match score_moves_at(position.game, position.next_turn, position.next_field, depth, &limit) {
+            Some(scores) => {
+                let mv = choose_move(&scores, settings.temperature);
+                let score = scores.iter()
+                    .find(|(m, _)|*m == mv)
+                    .map_or(0.0, |(_, score)|*score);
+
+                send(&format!("info depth {} score {:.2} move {}", depth, score, format_move(mv)));
+                best = Some(mv);
+            }
+            None => break,
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(text: &str) -> Result<Position, String> {
+        parse_position(text.split_whitespace())
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(position("startpos"), Ok(Position::start()));
+        assert_eq!(position("  startpos  "), Ok(Position::start()));
+
+        let mut played = Position::start();
+        played.play(parse_move("55").unwrap()).unwrap();
+        played.play(parse_move("51").unwrap()).unwrap();
+        assert_eq!(position("startpos moves 55 51"), Ok(played));
+        assert_eq!(position(&format!("fen {}", played)), Ok(played));
+
+        played.play(parse_move("15").unwrap()).unwrap();
+        assert_eq!(position(&format!("fen {} moves 15", position("startpos moves 55 51").unwrap())), Ok(played));
+
+        assert!(position("").is_err());
+        assert!(position("endpos").is_err());
+        assert!(position("startpos 55").is_err());
+        assert!(position("startpos moves 5").is_err());
+        assert_eq!(position("startpos moves 55 11"), Err(String::from("illegal move 11")));
+        assert!(position("fen x o 5").is_err());
+    }
+
+    #[test]
+    fn parses_go() {
+        let go = |text: &str|parse_go(text.split_whitespace());
+
+        assert_eq!(go(""), (EngineSettings::exact(DEFAULT_DEPTH), false));
+        assert_eq!(go("depth 3").0.depth, 3);
+        assert_eq!(go("level 4"), (EngineSettings::preset(4), false));
+
+        let mut expected = EngineSettings::preset(4);
+        expected.depth = 2;
+        expected.time_limit = Some(500);
+        assert_eq!(go("movetime 500 depth 2 level 4"), (expected, false));
+        assert_eq!(go("level 4 depth 2 movetime 500"), (expected, false));
+
+        let (settings, infinite) = go("movetime 500 infinite");
+        assert!(infinite);
+        assert_eq!((settings.depth, settings.time_limit), (MAX_DEPTH, None));
+    }
+}
@@ -0,0 +1,142 @@
+//! Renders every position of a game to PNG images or an animated GIF, using the SVG
+//! diagrams of `svg` and drawing them on the CPU.
+
+use crate::data::{Position, Move};
+use crate::svg::{Annotations, render};
+use gif::{Encoder, Frame, Repeat};
+use tiny_skia::{Pixmap, Transform};
+use usvg::{Tree, Options, FitTo};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// The width and height of a frame in pixels.
+pub const DEFAULT_FRAME_SIZE: u32 = 450;
+/// Larger frames need hundreds of megabytes for a single image.
+pub const MAX_FRAME_SIZE: u32 = 4096;
+/// How long a position of the animation is shown, in milliseconds.
+pub const DEFAULT_FRAME_DELAY: u64 = 1000;
+/// The final position is shown this many times longer before the animation repeats.
+const FINAL_FRAME_FACTOR: u64 = 3;
+
+/// The position before every move and after the last one, with the move leading to it.
+/// `moves` have to be legal.
+pub fn positions(moves: &[Move]) -> Vec<(Position, Option<Move>)> {
+    let mut position = Position::start();
+    let mut positions = vec![(position, None)];
+
+    for mv in moves {
+        if position.play(*mv).is_err() {
+            break;
+        }
+        positions.push((position, Some(*mv)));
+    }
+    positions
+}
+
+/// Draws `position` as a square of `size` pixels.
+pub fn render_frame(position: &Position, last_move: Option<Move>, size: u32) -> io::Result<Pixmap> {
+    check_size(size)?;
+    let svg = render(position, &Annotations {last_move, arrows: Vec::new()});
+    let tree = Tree::from_str(&svg, &Options::default().to_ref())
+        .map_err(|err|io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+    let mut pixmap = Pixmap::new(size, size)
+        .ok_or_else(||io::Error::other("could not allocate the frame"))?;
+    resvg::render(&tree, FitTo::Size(size, size), Transform::default(), pixmap.as_mut())
+        .ok_or_else(||io::Error::other("could not render the board"))?;
+    Ok(pixmap)
+}
+
+/// Writes a PNG for every position of the game into the directory `dir`,
+/// `000.png` being the empty board. Returns the number of images.
+/// The directory has to be empty, frames of another game would be mixed in.
+pub fn write_frames(dir: &Path, moves: &[Move], size: u32) -> io::Result<usize> {
+    check_size(size)?;
+    if fs::read_dir(dir).is_ok_and(|mut entries|entries.next().is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("the directory {} is not empty", dir.display()),
+        ));
+    }
+    fs::create_dir_all(dir)?;
+    let positions = positions(moves);
+
+    for (index, (position, last_move)) in positions.iter().enumerate() {
+        render_frame(position, *last_move, size)?
+            .save_png(dir.join(format!("{:03}.png", index)))
+            .map_err(|err|io::Error::other(err.to_string()))?;
+    }
+    Ok(positions.len())
+}
+
+/// Writes the game as an animated GIF showing every position for `delay` milliseconds.
+/// Returns the number of frames.
+pub fn write_gif(path: &Path, moves: &[Move], size: u32, delay: u64) -> io::Result<usize> {
+    check_size(size)?;
+    let positions = positions(moves);
+    let side = size as u16;
+
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), side, side, &[]).map_err(to_io)?;
+    encoder.set_repeat(Repeat::Infinite).map_err(to_io)?;
+
+    for (index, (position, last_move)) in positions.iter().enumerate() {
+        let pixmap = render_frame(position, *last_move, side as u32)?;
+        // The background is opaque, the premultiplied colors are the plain ones.
+        let mut pixels = pixmap.take();
+        let mut frame = Frame::from_rgba_speed(side, side, &mut pixels, 10);
+
+        let shown = if index + 1 == positions.len() {delay * FINAL_FRAME_FACTOR} else {delay};
+        // In hundredths of a second.
+        frame.delay = (shown / 10).min(u16::MAX as u64) as u16;
+        encoder.write_frame(&frame).map_err(to_io)?;
+    }
+    Ok(positions.len())
+}
+
+fn check_size(size: u32) -> io::Result<()> {
+    if size == 0 || size > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the frame size has to be between 1 and {} pixels", MAX_FRAME_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+fn to_io(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::parse_move;
+    use std::env;
+
+    #[test]
+    fn rejects_bad_frame_sizes() {
+        let path = env::temp_dir().join(format!("export-size-{}.gif", std::process::id()));
+        for size in [0, MAX_FRAME_SIZE + 1, u16::MAX as u32] {
+            let err = write_gif(&path, &[], size, DEFAULT_FRAME_DELAY).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn refuses_a_directory_with_old_frames() {
+        let dir = env::temp_dir().join(format!("export-frames-{}", std::process::id()));
+        let moves = [parse_move("55").unwrap()];
+
+        assert_eq!(write_frames(&dir, &moves, 20).unwrap(), 2);
+        assert!(dir.join("001.png").exists());
+        let err = write_frames(&dir, &[], 20).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
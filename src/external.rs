@@ -0,0 +1,205 @@
+use crate::data::{Position, Move, format_move, parse_move};
+use crate::ai::MAKE_MOVE;
+use druid::{ExtEventSink, Selector, Target};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+/// The thinking time external engines get for every move.
+pub const MOVE_TIME: u64 = 2000;
+
+/// How much longer than `MOVE_TIME` we wait for an answer before giving up.
+const GRACE_TIME: Duration = Duration::from_secs(5);
+
+/// The external engine did not deliver a legal move in the search with the id, with a
+/// description what went wrong.
+pub static ENGINE_FAILED: Selector<(u64, String)> = Selector::new("de.ticktacktoe_ultimate.engine_failed");
+
+/// The command starting this program as engine, the default for external engines.
+pub fn default_command() -> String {
+    match std::env::current_exe() {
+        Ok(path) if path.to_string_lossy().contains(char::is_whitespace) => format!("\"{}\" engine", path.display()),
+        Ok(path) => format!("{} engine", path.display()),
+        Err(_) => String::from("ticktacktoe_ultimate engine"),
+    }
+}
+
+/// Splits `command` into the program and its arguments at whitespace. Parts containing
+/// whitespace are enclosed in `"` or `'`.
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut part: Option<String> = None;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => part.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                part.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => parts.extend(part.take()),
+            None => part.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(String::from("has an unclosed quote"));
+    }
+    parts.extend(part);
+
+    Ok(parts)
+}
+
+/// Asks the engine started by `command` for a move, the reply is submitted as `MAKE_MOVE`
+/// with the id `search`, a failure as `ENGINE_FAILED`.
+///
+/// The engine process is started at the first request and reused for later moves.
+pub fn request_move(command: String, position: Position, search: u64, sink: ExtEventSink) {
+    spawn(move ||{
+        match ask(&command, position) {
+            Ok(mv) => sink.submit_command(MAKE_MOVE, (search, mv), Target::Global).unwrap(),
+            Err(err) => sink.submit_command(ENGINE_FAILED, (search, format!("{} {}", command, err)), Target::Global).unwrap(),
+        }
+    });
+}
+
+/// The running engines by their command. The map is only locked to look an engine up,
+/// talking to an engine locks just that engine.
+fn engines() -> MutexGuard<'static, HashMap<String, Arc<Mutex<EngineProcess>>>> {
+    static ENGINES: OnceLock<Mutex<HashMap<String, Arc<Mutex<EngineProcess>>>>> = OnceLock::new();
+
+    ENGINES.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned|poisoned.into_inner())
+}
+
+/// The engine started by `command`, it is started if it isn't running yet.
+fn engine(command: &str) -> Result<Arc<Mutex<EngineProcess>>, String> {
+    if let Some(engine) = engines().get(command) {
+        return Ok(engine.clone());
+    }
+
+    let engine = Arc::new(Mutex::new(EngineProcess::start(command)?));
+    // Another request may have started the same engine meanwhile, the first one is kept.
+    Ok(engines().entry(command.to_string()).or_insert(engine).clone())
+}
+
+fn ask(command: &str, position: Position) -> Result<Move, String> {
+    let engine = engine(command)?;
+
+    let result = engine.lock()
+        .unwrap_or_else(|poisoned|poisoned.into_inner())
+        .best_move(position);
+    if result.is_err() {
+        // A misbehaving engine is restarted for the next request.
+        let mut engines = engines();
+        if engines.get(command).is_some_and(|running|Arc::ptr_eq(running, &engine)) {
+            engines.remove(command);
+        }
+    }
+    result
+}
+
+struct EngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl EngineProcess {
+    fn start(command: &str) -> Result<Self, String> {
+        let parts = split_command(command)?;
+        let (program, args) = parts.split_first().ok_or("is empty")?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err|format!("could not be started: {}", err))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = channel();
+        spawn(move ||{
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = EngineProcess {child, stdin, lines};
+        engine.send("uti")?;
+        engine.expect("utiok", GRACE_TIME)?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_|self.stdin.flush())
+            .map_err(|_|String::from("crashed"))
+    }
+
+    /// Returns the first line starting with `prefix`, skipping everything else.
+    fn expect(&mut self, prefix: &str, timeout: Duration) -> Result<String, String> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) if line.starts_with(prefix) => return Ok(line),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return Err(String::from("did not answer in time")),
+                Err(RecvTimeoutError::Disconnected) => return Err(String::from("crashed")),
+            }
+        }
+    }
+
+    fn best_move(&mut self, position: Position) -> Result<Move, String> {
+        self.send(&format!("position fen {}", position))?;
+        self.send(&format!("go movetime {}", MOVE_TIME))?;
+
+        let line = self.expect("bestmove", Duration::from_millis(MOVE_TIME) + GRACE_TIME)?;
+        let text = line["bestmove".len()..].trim();
+
+        let mv = parse_move(text).ok_or_else(||format!("sent the invalid move '{}'", text))?;
+        if !position.is_legal(mv) {
+            return Err(format!("sent the illegal move {}", format_move(mv)));
+        }
+        Ok(mv)
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split_command("  engine  --level 5 "), Ok(vec![String::from("engine"), String::from("--level"), String::from("5")]));
+        assert_eq!(
+            split_command("\"/opt/my engines/uti\" 'a b' \"\""),
+            Ok(vec![String::from("/opt/my engines/uti"), String::from("a b"), String::new()]),
+        );
+        assert_eq!(split_command(""), Ok(Vec::new()));
+        assert!(split_command("\"engine").is_err());
+    }
+}
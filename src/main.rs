@@ -1,11 +1,30 @@
 use druid::{WindowDesc, AppLauncher, AppDelegate, Handled, Env, Command, Target, DelegateCtx};
 use crate::ui::main_ui;
-use crate::data::{GameData, Slot};
-use crate::ai::MAKE_MOVE;
+use crate::data::GameData;
+use crate::ai::{MAKE_MOVE, ANALYSIS, is_current_search};
+use crate::external::ENGINE_FAILED;
+use crate::network::{CONNECTED, REMOTE_MOVE, REMOTE_ACTION, RESUMED, WATCHING, DISCONNECTED, disconnect};
+use crate::ai::book::{OpeningBook, DEFAULT_BOOK_PATH};
+use crate::series::GAME_OVER;
+use crate::settings::{Settings, DEFAULT_WINDOW_SIZE};
+use std::str::FromStr;
 
 pub mod data;
 mod ui;
 mod ai;
+mod engine;
+mod external;
+mod network;
+mod server;
+mod api;
+mod clock;
+mod series;
+mod tui;
+mod cli;
+mod svg;
+mod export;
+mod settings;
+mod theme;
 
 struct MyDelegate;
 
@@ -18,19 +37,55 @@ impl AppDelegate<GameData> for MyDelegate {
         data: &mut GameData,
         _env: &Env,
     ) -> Handled {
-        if let Some((outer_pos, inner_pos)) = cmd.get(MAKE_MOVE) {
-            let next = data.next_turn;
+        if let Some((search, mv)) = cmd.get(MAKE_MOVE) {
+            // Moves of searches for an earlier state of the game are dropped.
+            if is_current_search(*search) && !data.is_over() && !data.is_paused() && data.game.is_legal(data.next_field, *mv) {
+                data.play(*mv);
+            }
 
-            let mut inner = data.game[*outer_pos];
-            inner.set(*inner_pos, Some(next));
-            data.game.set(*outer_pos, inner);
-            data.next_turn = data.next_turn.other();
-            let next_pos = if data.game[*inner_pos].has_free() {
-                Some(*inner_pos)
-            } else {
-                None
-            };
-            data.next_field = next_pos;
+            Handled::Yes
+        } else if let Some((key, solution)) = cmd.get(ANALYSIS) {
+            if *key == data.position_key() {
+                data.analysis = Some(*solution);
+            }
+
+            Handled::Yes
+        } else if cmd.is(GAME_OVER) {
+            data.record_result();
+
+            Handled::Yes
+        } else if let Some((search, error)) = cmd.get(ENGINE_FAILED) {
+            data.engine_failed(*search, error);
+
+            Handled::Yes
+        } else if let Some(peer) = cmd.get(CONNECTED) {
+            data.connected(*peer);
+
+            Handled::Yes
+        } else if let Some((peer, moves)) = cmd.get(RESUMED) {
+            data.resumed(*peer, moves);
+
+            Handled::Yes
+        } else if let Some(moves) = cmd.get(WATCHING) {
+            data.watching(moves);
+
+            Handled::Yes
+        } else if let Some(mv) = cmd.get(REMOTE_MOVE) {
+            if let Err(reason) = data.remote_move(*mv) {
+                disconnect();
+                data.disconnected(&reason);
+            }
+
+            Handled::Yes
+        } else if let Some((action, mark)) = cmd.get(REMOTE_ACTION) {
+            if let Err(reason) = data.remote_action(*action, *mark) {
+                disconnect();
+                data.disconnected(&reason);
+            }
+
+            Handled::Yes
+        } else if let Some(reason) = cmd.get(DISCONNECTED) {
+            data.disconnected(reason);
 
             Handled::Yes
         } else {
@@ -39,14 +94,71 @@ impl AppDelegate<GameData> for MyDelegate {
     }
 }
 
+/// Returns the value following `--name`, if present. Exits with 1 if the value is
+/// missing or invalid, instead of falling back to the default.
+fn option<T: FromStr>(args: &[String], name: &str) -> Option<T> {
+    let index = args.iter().position(|arg|arg.strip_prefix("--") == Some(name))?;
+
+    match args.get(index + 1).map(|value|(value, value.parse())) {
+        Some((_, Ok(value))) => Some(value),
+        Some((value, Err(_))) => exit_with(&format!("invalid value '{}' for --{}", value, name)),
+        None => exit_with(&format!("missing value for --{}", name)),
+    }
+}
+
+fn exit_with(error: &str) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+fn generate_book(args: &[String]) {
+    let path = option(args, "out").unwrap_or_else(||String::from(DEFAULT_BOOK_PATH));
+    let games = option(args, "games").unwrap_or(50);
+    let ahead = option(args, "ahead").unwrap_or(2);
+    let plies = option(args, "plies").unwrap_or(8);
+    let exploration = option(args, "exploration").unwrap_or(0.2);
+    if !(0.0..=1.0).contains(&exploration) {
+        exit_with("--exploration must be between 0 and 1");
+    }
+
+    let book = OpeningBook::generate(games, ahead, plies, exploration);
+
+    if let Err(err) = book.save(&path) {
+        eprintln!("could not write {}: {}", path, err);
+        std::process::exit(1);
+    }
+    println!("wrote {} positions to {}", book.len(), path);
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("book") => generate_book(&args[1..]),
+        Some("engine") => engine::run(),
+        Some("server") => server::run(option(&args[1..], "port")),
+        Some("api") => api::run(option(&args[1..], "port")),
+        Some("play") => cli::play(option(&args[1..], "moves")),
+        Some("svg") => cli::svg(option(&args[1..], "moves"), option(&args[1..], "arrows"), option(&args[1..], "out")),
+        Some("export") => cli::export(option(&args[1..], "moves"), option(&args[1..], "out"), option(&args[1..], "size"), option(&args[1..], "delay")),
+        Some("tui") => tui::run(option(&args[1..], "ai"), option(&args[1..], "level")),
+        _ => launch(),
+    }
+}
+
+fn launch() {
+    let mut data = GameData::local(DEFAULT_WINDOW_SIZE);
+    Settings::load().apply(&mut data);
+    data.start();
+
     let window = WindowDesc::new(main_ui)
-        .with_min_size((560.0, 680.0))
+        .window_size(data.window_size)
+        .with_min_size((560.0, 800.0))
         .resizable(true)
         .title("Tick Tack Toe Ultimate");
 
     AppLauncher::with_window(window)
         .delegate(MyDelegate)
-        .launch(GameData::local())
+        .launch(data)
         .expect("launch failed!");
 }
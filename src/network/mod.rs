@@ -0,0 +1,349 @@
+use crate::data::{Mark, Move};
+use crate::network::protocol::{Message, Action, read_message, write_message, DEFAULT_PORT};
+use druid::{ExtEventSink, Selector, Target};
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream, SocketAddr, Ipv4Addr, ToSocketAddrs, Shutdown};
+use std::sync::Mutex;
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+pub mod protocol;
+
+/// A peer connected, the payload is the mark the peer plays.
+pub static CONNECTED: Selector<Mark> = Selector::new("de.ticktacktoe_ultimate.connected");
+
+/// The peer made a move, it still has to be checked against the rules.
+pub static REMOTE_MOVE: Selector<Move> = Selector::new("de.ticktacktoe_ultimate.remote_move");
+
+/// The peer, or a player of a watched game, did something besides moving.
+pub static REMOTE_ACTION: Selector<(Action, Mark)> = Selector::new("de.ticktacktoe_ultimate.remote_action");
+
+/// The lost connection to the server was resumed, the payload is the mark the peer plays
+/// and all moves of the game so far.
+pub static RESUMED: Selector<(Mark, Vec<Move>)> = Selector::new("de.ticktacktoe_ultimate.resumed");
+
+/// Watching a game on a server started, the payload are the moves played so far.
+pub static WATCHING: Selector<Vec<Move>> = Selector::new("de.ticktacktoe_ultimate.watching");
+
+/// The connection ended, with a description why.
+pub static DISCONNECTED: Selector<String> = Selector::new("de.ticktacktoe_ultimate.disconnected");
+
+/// How often resuming a lost game on a server is tried, before giving up.
+const RESUME_ATTEMPTS: u32 = 5;
+
+/// The time between two attempts to resume a game.
+const RESUME_DELAY: Duration = Duration::from_secs(2);
+
+/// The one connection to a peer. Every call of `host`, `join` and `disconnect` starts
+/// a new generation, threads of older generations stop on their own.
+struct Connection {
+    generation: u64,
+    stream: Option<TcpStream>,
+}
+
+static CONNECTION: Mutex<Connection> = Mutex::new(Connection {generation: 0, stream: None});
+
+fn next_generation() -> u64 {
+    let mut connection = CONNECTION.lock().unwrap();
+    if let Some(stream) = connection.stream.take() {
+        let _ = write_message(&mut &stream, &Message::Bye);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+    connection.generation += 1;
+    connection.generation
+}
+
+fn is_current(generation: u64) -> bool {
+    CONNECTION.lock().unwrap().generation == generation
+}
+
+/// Splits `server:port/room` into the server address and the room.
+fn split_room(address: &str) -> Option<(String, String)> {
+    let (server, room) = address.split_once('/')?;
+    Some((server.to_string(), room.to_string()))
+}
+
+/// Waits for a peer on the port of `address`, the host plays `Mark::Cross`.
+///
+/// With an address like `server:port/room`, a room is opened on that server instead.
+pub fn host(address: &str, sink: ExtEventSink) {
+    let generation = next_generation();
+
+    if let Some((server, room)) = split_room(address) {
+        spawn(move ||start_on_server(server, Some(Message::Create(room)), generation, sink));
+        return;
+    }
+
+    let port = address.rsplit(':')
+        .next()
+        .and_then(|port|port.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+
+    spawn(move ||{
+        match accept(port, generation) {
+            Ok(Some(stream)) => {
+                let started = write_message(&mut &stream, &Message::Start(Mark::Circle));
+                match started {
+                    Ok(()) => connected(stream, None, Joined::Play(Mark::Cross), generation, sink),
+                    Err(err) => disconnected(format!("could not start the game: {}", err), generation, sink),
+                }
+            }
+            Ok(None) => {}
+            Err(err) => disconnected(format!("could not host on port {}: {}", port, err), generation, sink),
+        }
+    });
+}
+
+/// Accepts the first peer, `None` if hosting was cancelled in the meantime.
+fn accept(port: u16, generation: u64) -> io::Result<Option<TcpStream>> {
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))?;
+    listener.set_nonblocking(true)?;
+
+    while is_current(generation) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                return Ok(Some(stream));
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => sleep(Duration::from_millis(100)),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Connects to the host at `address`, or joins the room of a server with `server:port/room`.
+pub fn join(address: &str, sink: ExtEventSink) {
+    let generation = next_generation();
+    let address = address.to_string();
+
+    spawn(move ||{
+        match split_room(&address) {
+            Some((server, room)) => start_on_server(server, Some(Message::Join(room)), generation, sink),
+            // A host does not expect a request, it starts the game right away.
+            None => start_on_server(address, None, generation, sink),
+        }
+    });
+}
+
+/// Follows the game in a room of a server, the address looks like `server:port/room`.
+pub fn watch(address: &str, sink: ExtEventSink) {
+    let generation = next_generation();
+    let address = address.to_string();
+
+    spawn(move ||{
+        match split_room(&address) {
+            Some((server, room)) => start_on_server(server, Some(Message::Watch(room)), generation, sink),
+            None => disconnected(String::from("the address needs a room, like server:port/room"), generation, sink),
+        }
+    });
+}
+
+enum Joined {
+    /// Playing the mark against the peer.
+    Play(Mark),
+    /// Watching a game with these moves so far.
+    Watch(Vec<Move>),
+}
+
+/// Connects, sends `request` and waits until the game starts.
+fn start_on_server(address: String, request: Option<Message>, generation: u64, sink: ExtEventSink) {
+    let result = connect(&address).and_then(|mut stream|{
+        if let Some(request) = request {
+            write_message(&mut stream, &request)?;
+        }
+        let joined = wait_for_start(&mut stream, generation)?;
+        Ok(joined.map(|joined|(stream, joined)))
+    });
+
+    match result {
+        Ok(Some((stream, joined))) => connected(stream, Some(address), joined, generation, sink),
+        Ok(None) => {}
+        Err(err) => disconnected(format!("could not play on {}: {}", address, err), generation, sink),
+    }
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let address = address.to_socket_addrs()?
+        .next()
+        .ok_or_else(||io::Error::new(ErrorKind::NotFound, "unknown address"))?;
+
+    TcpStream::connect_timeout(&address, Duration::from_secs(5))
+}
+
+/// Waits for the start of the game, `None` if waiting was cancelled in the meantime.
+fn wait_for_start(stream: &mut TcpStream, generation: u64) -> io::Result<Option<Joined>> {
+    while is_current(generation) {
+        stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+
+        // Only peeking runs into the timeout, so a message is never read in parts.
+        match stream.peek(&mut [0]) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "the connection was closed")),
+            Ok(_) => {
+                stream.set_read_timeout(None)?;
+                match read_message(stream)? {
+                    Message::Start(mark) => return Ok(Some(Joined::Play(mark))),
+                    Message::History(moves) => return Ok(Some(Joined::Watch(moves))),
+                    Message::Error(text) => return Err(io::Error::other(text)),
+                    _ => {}
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Makes `stream` the connection to the peer, unless the generation is outdated.
+fn attach(stream: &TcpStream, generation: u64) -> bool {
+    let mut connection = CONNECTION.lock().unwrap();
+    if connection.generation != generation {
+        return false;
+    }
+    match stream.try_clone() {
+        Ok(writer) => {
+            connection.stream = Some(writer);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// `server` is the address of the server the game is played on, if any.
+fn connected(stream: TcpStream, server: Option<String>, joined: Joined, generation: u64, sink: ExtEventSink) {
+    if !attach(&stream, generation) {
+        return;
+    }
+    match joined {
+        Joined::Play(mark) => sink.submit_command(CONNECTED, mark.other(), Target::Global).unwrap(),
+        Joined::Watch(moves) => sink.submit_command(WATCHING, moves, Target::Global).unwrap(),
+    }
+
+    receive(stream, server, generation, sink);
+}
+
+fn receive(mut stream: TcpStream, server: Option<String>, generation: u64, sink: ExtEventSink) {
+    // Set by the server when the game starts, to resume it after losing the connection.
+    let mut token = None;
+
+    loop {
+        match read_message(&mut stream) {
+            Ok(Message::Move(mv)) => {
+                if !is_current(generation) {
+                    return;
+                }
+                sink.submit_command(REMOTE_MOVE, mv, Target::Global).unwrap();
+            }
+            Ok(Message::Action(action, mark)) => {
+                if !is_current(generation) {
+                    return;
+                }
+                sink.submit_command(REMOTE_ACTION, (action, mark), Target::Global).unwrap();
+            }
+            Ok(Message::Session(session)) => token = Some(session),
+            Ok(Message::Bye) => {
+                disconnected(String::from("the opponent left the game"), generation, sink);
+                return;
+            }
+            Ok(Message::Error(text)) => {
+                disconnected(text, generation, sink);
+                return;
+            }
+            Ok(_) => {
+                disconnected(String::from("the opponent sent an unexpected message"), generation, sink);
+                return;
+            }
+            Err(_) => {
+                if !is_current(generation) {
+                    return;
+                }
+                let resumed = match (&server, &token) {
+                    (Some(server), Some(token)) => resume(server, token, generation),
+                    _ => None,
+                };
+                match resumed {
+                    Some((new_stream, mark, moves)) => {
+                        if !attach(&new_stream, generation) {
+                            return;
+                        }
+                        sink.submit_command(RESUMED, (mark.other(), moves), Target::Global).unwrap();
+                        stream = new_stream;
+                    }
+                    None => {
+                        disconnected(String::from("the connection was lost"), generation, sink);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Tries to continue the game of the session on the server, returns the new connection,
+/// the own mark and all moves so far.
+fn resume(server: &str, token: &str, generation: u64) -> Option<(TcpStream, Mark, Vec<Move>)> {
+    for _ in 0..RESUME_ATTEMPTS {
+        sleep(RESUME_DELAY);
+        if !is_current(generation) {
+            return None;
+        }
+
+        let reply = connect(server).and_then(|mut stream|{
+            write_message(&mut stream, &Message::Resume(token.to_string()))?;
+            stream.set_read_timeout(Some(RESUME_DELAY))?;
+            let reply = read_message(&mut stream)?;
+            stream.set_read_timeout(None)?;
+            Ok((stream, reply))
+        });
+
+        match reply {
+            Ok((stream, Message::Resumed(mark, moves))) => return Some((stream, mark, moves)),
+            // The server does not know the session anymore, trying again won't help.
+            Ok(_) => return None,
+            Err(_) => {}
+        }
+    }
+    None
+}
+
+fn disconnected(reason: String, generation: u64, sink: ExtEventSink) {
+    let mut connection = CONNECTION.lock().unwrap();
+    if connection.generation != generation {
+        return;
+    }
+    if let Some(stream) = connection.stream.take() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+    drop(connection);
+
+    sink.submit_command(DISCONNECTED, reason, Target::Global).unwrap();
+}
+
+/// Ends the connection to the peer, if there is one.
+pub fn disconnect() {
+    let is_connected = {
+        let connection = CONNECTION.lock().unwrap();
+        connection.stream.is_some()
+    };
+    if is_connected {
+        next_generation();
+    }
+}
+
+pub fn send_move(mv: Move) {
+    send(&Message::Move(mv));
+}
+
+/// Tells the peer about an action of the player of `mark`.
+pub fn send_action(action: Action, mark: Mark) {
+    send(&Message::Action(action, mark));
+}
+
+fn send(message: &Message) {
+    let mut connection = CONNECTION.lock().unwrap();
+    if let Some(stream) = connection.stream.as_mut() {
+        // A failed write also ends the receiving thread, which reports it.
+        let _ = write_message(stream, message);
+    }
+}
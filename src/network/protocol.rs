@@ -0,0 +1,163 @@
+use crate::data::{Mark, Move, format_move, parse_move};
+use std::io::{self, Read, Write, ErrorKind};
+
+/// Frames larger than this are rejected, no valid message comes close to it.
+const MAX_FRAME: usize = 64 * 1024;
+
+/// The default port for hosting games.
+pub const DEFAULT_PORT: u16 = 4747;
+
+/// Something a player does besides moving.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Action {
+    Resign,
+    /// Offers a draw, or accepts the draw offered by the opponent.
+    OfferDraw,
+    /// Asks for a new game with swapped marks, or agrees to the one asked for by the opponent.
+    Rematch,
+}
+
+/// A message between two players or a player and the server. Every message is sent as
+/// one frame, a big endian `u32` length followed by the message as UTF-8 text.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Message {
+    /// Sent by the host: a new game starts and the receiver plays `Mark`.
+    Start(Mark),
+    Move(Move),
+    /// The sender leaves the game.
+    Bye,
+    /// Asks the server to open a room and wait for an opponent there.
+    Create(String),
+    /// Asks the server to join the waiting player in a room.
+    Join(String),
+    /// Asks the server for the rooms with a waiting player.
+    List,
+    Rooms(Vec<String>),
+    /// The server rejected the last request.
+    Error(String),
+    /// Asks the server to follow the game in a room without playing.
+    Watch(String),
+    /// Sent by the server to spectators: all moves of the game so far.
+    History(Vec<Move>),
+    /// Sent by the server with `Start`: the token to resume the game after losing the connection.
+    Session(String),
+    /// Asks the server to continue the game of a session.
+    Resume(String),
+    /// Sent by the server to a resumed player: its mark and all moves of the game so far.
+    Resumed(Mark, Vec<Move>),
+    /// The player of `Mark` did something besides moving.
+    Action(Action, Mark),
+}
+
+impl Message {
+    fn encode(&self) -> String {
+        match self {
+            Message::Start(mark) => format!("start {}", mark_to_str(*mark)),
+            Message::Move(mv) => format!("move {}", format_move(*mv)),
+            Message::Bye => String::from("bye"),
+            Message::Create(room) => format!("create {}", room),
+            Message::Join(room) => format!("join {}", room),
+            Message::List => String::from("list"),
+            Message::Rooms(rooms) => format!("rooms {}", rooms.join(" ")),
+            Message::Error(text) => format!("error {}", text),
+            Message::Watch(room) => format!("watch {}", room),
+            Message::History(moves) => {
+                let moves: Vec<_> = moves.iter().map(|mv|format_move(*mv)).collect();
+                format!("history {}", moves.join(" "))
+            }
+            Message::Action(action, mark) => {
+                let name = match action {
+                    Action::Resign => "resign",
+                    Action::OfferDraw => "draw",
+                    Action::Rematch => "rematch",
+                };
+                format!("{} {}", name, mark_to_str(*mark))
+            }
+            Message::Session(token) => format!("session {}", token),
+            Message::Resume(token) => format!("resume {}", token),
+            Message::Resumed(mark, moves) => {
+                let moves: Vec<_> = moves.iter().map(|mv|format_move(*mv)).collect();
+                format!("resumed {} {}", mark_to_str(*mark), moves.join(" "))
+            }
+        }
+    }
+
+    fn decode(text: &str) -> Option<Message> {
+        let mut words = text.split_whitespace();
+
+        let message = match words.next()? {
+            "rooms" => return Some(Message::Rooms(words.map(String::from).collect())),
+            "error" => return Some(Message::Error(words.collect::<Vec<_>>().join(" "))),
+            "history" => return words.map(parse_move).collect::<Option<_>>().map(Message::History),
+            "resumed" => {
+                let mark = mark_from_str(words.next()?)?;
+                return words.map(parse_move).collect::<Option<_>>().map(|moves|Message::Resumed(mark, moves));
+            }
+            "resign" => Message::Action(Action::Resign, mark_from_str(words.next()?)?),
+            "draw" => Message::Action(Action::OfferDraw, mark_from_str(words.next()?)?),
+            "rematch" => Message::Action(Action::Rematch, mark_from_str(words.next()?)?),
+            "session" => Message::Session(words.next()?.to_string()),
+            "resume" => Message::Resume(words.next()?.to_string()),
+            "watch" => Message::Watch(words.next()?.to_string()),
+            "create" => Message::Create(words.next()?.to_string()),
+            "join" => Message::Join(words.next()?.to_string()),
+            "list" => Message::List,
+            "start" => Message::Start(mark_from_str(words.next()?)?),
+            "move" => Message::Move(parse_move(words.next()?)?),
+            "bye" => Message::Bye,
+            _ => return None,
+        };
+
+        if words.next().is_none() {
+            Some(message)
+        } else {
+            None
+        }
+    }
+}
+
+/// Room names are single words, so they fit into the messages.
+pub fn is_valid_room(room: &str) -> bool {
+    !room.is_empty() && room.len() <= 32 && !room.contains(char::is_whitespace)
+}
+
+pub fn mark_to_str(mark: Mark) -> &'static str {
+    match mark {
+        Mark::Cross => "x",
+        Mark::Circle => "o",
+    }
+}
+
+pub fn mark_from_str(text: &str) -> Option<Mark> {
+    match text {
+        "x" => Some(Mark::Cross),
+        "o" => Some(Mark::Circle),
+        _ => None,
+    }
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    let text = message.encode();
+
+    writer.write_all(&(text.len() as u32).to_be_bytes())?;
+    writer.write_all(text.as_bytes())?;
+    writer.flush()
+}
+
+pub fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(io::Error::new(ErrorKind::InvalidData, "frame too large"));
+    }
+
+    let mut text = vec![0; length];
+    reader.read_exact(&mut text)?;
+
+    String::from_utf8(text)
+        .ok()
+        .and_then(|text|Message::decode(&text))
+        .ok_or_else(||io::Error::new(ErrorKind::InvalidData, "invalid message"))
+}
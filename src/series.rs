@@ -0,0 +1,234 @@
+use crate::data::{Mark, Opponent};
+use druid::{Data, Selector};
+use std::sync::Arc;
+
+/// A game ended, it is counted for the running series.
+pub static GAME_OVER: Selector<()> = Selector::new("de.ticktacktoe_ultimate.game_over");
+
+pub const MAX_SERIES_LENGTH: u64 = 15;
+
+/// How a game of the series ended.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum GameResult {
+    FirstWon,
+    SecondWon,
+    Draw,
+}
+
+/// Several games between the same players, alternating who plays the crosses.
+#[derive(Clone, Data)]
+pub struct Series {
+    pub best_of: u64,
+    /// The players, `first` plays the crosses in the first game.
+    pub first: Option<Opponent>,
+    pub second: Option<Opponent>,
+    /// The result of every finished game.
+    pub results: Arc<Vec<GameResult>>,
+    /// Whether the current game is counted already.
+    counted: bool,
+}
+
+impl Series {
+    pub fn new(best_of: u64, first: Option<Opponent>, second: Option<Opponent>) -> Self {
+        Series {
+            best_of: best_of.max(1),
+            first,
+            second,
+            results: Arc::new(Vec::new()),
+            counted: false,
+        }
+    }
+
+    /// The players of the crosses and the circles in the current game.
+    pub fn players(&self) -> (Option<Opponent>, Option<Opponent>) {
+        if self.first_plays_cross() {
+            (self.first.clone(), self.second.clone())
+        } else {
+            (self.second.clone(), self.first.clone())
+        }
+    }
+
+    fn first_plays_cross(&self) -> bool {
+        let game = if self.counted {self.results.len() - 1} else {self.results.len()};
+        game % 2 == 0
+    }
+
+    /// The mark `first` plays in the current game.
+    pub fn first_mark(&self) -> Mark {
+        if self.first_plays_cross() {Mark::Cross} else {Mark::Circle}
+    }
+
+    /// Counts the current game, `winner` is `None` for a draw.
+    pub fn record(&mut self, winner: Option<Mark>) {
+        if self.counted {
+            return;
+        }
+        let result = match winner {
+            Some(mark) if mark == self.first_mark() => GameResult::FirstWon,
+            Some(_) => GameResult::SecondWon,
+            None => GameResult::Draw,
+        };
+        Arc::make_mut(&mut self.results).push(result);
+        self.counted = true;
+    }
+
+    /// Moves on to the next game, after the current one was counted.
+    pub fn next_game(&mut self) -> bool {
+        if !self.counted || self.is_decided() {
+            return false;
+        }
+        self.counted = false;
+        true
+    }
+
+    /// The points of `first` and `second`, a draw counts half.
+    pub fn score(&self) -> (f64, f64) {
+        self.results.iter().fold((0.0, 0.0), |(first, second), result|match result {
+            GameResult::FirstWon => (first + 1.0, second),
+            GameResult::SecondWon => (first, second + 1.0),
+            GameResult::Draw => (first + 0.5, second + 0.5),
+        })
+    }
+
+    /// One player can't be caught anymore, or all games are played.
+    pub fn is_decided(&self) -> bool {
+        let (first, second) = self.score();
+        let needed = self.best_of as f64 / 2.0;
+        first > needed || second > needed || self.results.len() as u64 >= self.best_of
+    }
+
+    /// Shows the series is over and it can't be continued.
+    pub fn is_finished(&self) -> bool {
+        self.counted && self.is_decided()
+    }
+
+    pub fn scoreboard(&self) -> String {
+        let (first, second) = self.score();
+        let game = if self.counted {self.results.len()} else {self.results.len() + 1};
+
+        format!(
+            "Best of {}, game {}: {} ({}) {} - {} {} ({})",
+            self.best_of, game,
+            player_name(&self.first, "Player 1"), mark_name(self.first_mark()),
+            format_points(first), format_points(second),
+            player_name(&self.second, "Player 2"), mark_name(self.first_mark().other()),
+        )
+    }
+
+    pub fn summary(&self) -> String {
+        let (first, second) = self.score();
+        let first_name = player_name(&self.first, "Player 1");
+        let second_name = player_name(&self.second, "Player 2");
+
+        let mut summary = if first > second {
+            format!("{} wins the series {} - {}", first_name, format_points(first), format_points(second))
+        } else if second > first {
+            format!("{} wins the series {} - {}", second_name, format_points(second), format_points(first))
+        } else {
+            format!("The series is drawn {} - {}", format_points(first), format_points(second))
+        };
+
+        for (game, result) in self.results.iter().enumerate() {
+            let result = match result {
+                GameResult::FirstWon => format!("{} won", first_name),
+                GameResult::SecondWon => format!("{} won", second_name),
+                GameResult::Draw => String::from("Draw"),
+            };
+            summary.push_str(&format!("\nGame {}: {}", game + 1, result));
+        }
+        summary
+    }
+}
+
+fn player_name(player: &Option<Opponent>, local: &str) -> String {
+    match player {
+        None => local.to_string(),
+        Some(Opponent::Ai {level}) => format!("AI level {}", level),
+        Some(Opponent::External {..}) => String::from("Engine"),
+        Some(Opponent::Remote) => String::from("Remote"),
+    }
+}
+
+fn mark_name(mark: Mark) -> &'static str {
+    match mark {
+        Mark::Cross => "X",
+        Mark::Circle => "O",
+    }
+}
+
+fn format_points(points: f64) -> String {
+    if points.fract() == 0.0 {
+        format!("{}", points)
+    } else {
+        format!("{:.1}", points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(best_of: u64) -> Series {
+        Series::new(best_of, None, Some(Opponent::Ai {level: 3}))
+    }
+
+    #[test]
+    fn alternates_the_colours() {
+        let mut series = series(5);
+        assert_eq!(series.first_mark(), Mark::Cross);
+        assert!(matches!(series.players(), (None, Some(Opponent::Ai {..}))));
+
+        series.record(Some(Mark::Cross));
+        // Still the finished game until the next one starts.
+        assert_eq!(series.first_mark(), Mark::Cross);
+        assert!(series.next_game());
+        assert_eq!(series.first_mark(), Mark::Circle);
+        assert!(matches!(series.players(), (Some(Opponent::Ai {..}), None)));
+
+        series.record(None);
+        assert!(series.next_game());
+        assert_eq!(series.first_mark(), Mark::Cross);
+    }
+
+    #[test]
+    fn scores_the_games() {
+        let mut series = series(5);
+        series.record(Some(Mark::Cross));
+        // A game is counted once.
+        series.record(Some(Mark::Circle));
+        series.next_game();
+        // The first player has the circles now.
+        series.record(Some(Mark::Cross));
+        series.next_game();
+        series.record(None);
+
+        assert_eq!(*series.results, vec![GameResult::FirstWon, GameResult::SecondWon, GameResult::Draw]);
+        assert_eq!(series.score(), (1.5, 1.5));
+        assert_eq!(series.scoreboard(), "Best of 5, game 3: Player 1 (X) 1.5 - 1.5 AI level 3 (O)");
+    }
+
+    #[test]
+    fn ends_when_a_player_cant_be_caught() {
+        let mut series = series(3);
+        series.record(Some(Mark::Cross));
+        assert!(!series.is_decided());
+        assert!(series.next_game());
+        series.record(Some(Mark::Circle));
+
+        assert!(series.is_finished());
+        assert!(!series.next_game());
+        assert_eq!(series.summary(), "Player 1 wins the series 2 - 0\nGame 1: Player 1 won\nGame 2: Player 1 won");
+    }
+
+    #[test]
+    fn ends_after_all_games() {
+        let mut series = series(2);
+        series.record(Some(Mark::Cross));
+        series.next_game();
+        series.record(Some(Mark::Cross));
+
+        assert!(series.is_finished());
+        assert_eq!(series.score(), (1.0, 1.0));
+        assert!(series.summary().starts_with("The series is drawn 1 - 1"));
+    }
+}
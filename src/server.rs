@@ -0,0 +1,453 @@
+use crate::data::{Position, Mark, Move, format_move};
+use crate::network::protocol::{Message, Action, read_message, write_message, is_valid_room, DEFAULT_PORT};
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream, SocketAddr, Ipv4Addr, Shutdown};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+use rand::random;
+
+/// How long a room is kept after both players lost the connection, so they can resume it.
+const ABANDONED_ROOM_TIME: Duration = Duration::from_secs(60);
+
+/// Hosts games for many clients. A client opens a room with `create`, the next client
+/// joining it plays against them. Every move is checked against the rules before it
+/// is passed on to the opponent and the spectators of the room.
+///
+/// A player losing the connection keeps the seat, it resumes the game with the
+/// token of its session.
+pub fn run(port: Option<u16>) {
+    let port = port.unwrap_or(DEFAULT_PORT);
+
+    match TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))) {
+        Ok(listener) => {
+            println!("listening on port {}", port);
+            serve(listener);
+        }
+        Err(err) => {
+            eprintln!("could not listen on port {}: {}", port, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn serve(listener: TcpListener) {
+    let lobby = Arc::new(Mutex::new(Lobby::default()));
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let lobby = lobby.clone();
+                spawn(move ||{
+                    if let Err(err) = handle_client(stream, lobby) {
+                        eprintln!("client failed: {}", err);
+                    }
+                });
+            }
+            Err(err) => eprintln!("could not accept a client: {}", err),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Lobby {
+    rooms: HashMap<String, Room>,
+    next_id: u64,
+}
+
+struct Room {
+    position: Position,
+    history: Vec<Move>,
+    /// The players of `Mark::Cross` and `Mark::Circle`, `None` while they are away.
+    players: [Option<Player>; 2],
+    /// The session tokens of the seats.
+    tokens: [String; 2],
+    started: bool,
+    /// Ended by resigning or agreeing to a draw.
+    ended: bool,
+    /// The mark which offered a draw, until the opponent moves.
+    draw_offer: Option<Mark>,
+    /// The mark which asked for a rematch.
+    rematch_offer: Option<Mark>,
+    spectators: Vec<Player>,
+    /// Since when both players are away.
+    abandoned: Option<Instant>,
+}
+
+struct Player {
+    id: u64,
+    stream: TcpStream,
+    /// The messages to the client, written by its own thread.
+    outbox: Sender<Message>,
+}
+
+fn seat(mark: Mark) -> usize {
+    match mark {
+        Mark::Cross => 0,
+        Mark::Circle => 1,
+    }
+}
+
+impl Room {
+    fn player(&mut self, mark: Mark) -> Option<&mut Player> {
+        self.players[seat(mark)].as_mut()
+    }
+
+    /// The mark of the client `id`, if it plays in this room.
+    fn mark_of(&self, id: u64) -> Option<Mark> {
+        [Mark::Cross, Mark::Circle].iter().copied()
+            .find(|mark|self.players[seat(*mark)].as_ref().is_some_and(|player|player.id == id))
+    }
+
+    fn is_waiting(&self) -> bool {
+        !self.started
+    }
+
+    fn is_empty(&self) -> bool {
+        self.players.iter().all(Option::is_none)
+    }
+
+    fn is_over(&self) -> bool {
+        self.ended || self.position.is_finished()
+    }
+
+    /// Sends `message` to everyone in the room except the player of `mark`.
+    fn relay(&mut self, mark: Mark, message: &Message) {
+        if let Some(opponent) = self.player(mark.other()) {
+            send(&opponent.outbox, message);
+        }
+        for spectator in &self.spectators {
+            send(&spectator.outbox, message);
+        }
+    }
+
+    fn act(&mut self, action: Action, mark: Mark) {
+        if action != Action::Rematch && self.is_over() {
+            // Too late, the game is over already.
+            return;
+        }
+        // Relayed first, a rematch swaps the seats.
+        self.relay(mark, &Message::Action(action, mark));
+
+        match action {
+            Action::Resign => self.ended = true,
+            Action::OfferDraw if self.draw_offer == Some(mark.other()) => self.ended = true,
+            Action::OfferDraw => self.draw_offer = Some(mark),
+            Action::Rematch if self.rematch_offer == Some(mark.other()) => self.rematch(),
+            Action::Rematch => self.rematch_offer = Some(mark),
+        }
+    }
+
+    /// Starts a new game in the room, the players swap their marks.
+    fn rematch(&mut self) {
+        self.position = Position::start();
+        self.history.clear();
+        self.ended = false;
+        self.draw_offer = None;
+        self.rematch_offer = None;
+        self.players.swap(0, 1);
+        self.tokens.swap(0, 1);
+    }
+}
+
+fn new_token() -> String {
+    format!("{:016x}", random::<u64>())
+}
+
+/// Queues `message` for the client of `outbox`, the lobby is never locked while writing.
+fn send(outbox: &Sender<Message>, message: &Message) {
+    // A client which can't be reached is noticed by its own thread.
+    let _ = outbox.send(message.clone());
+}
+
+/// Writes the messages of `outbox` to the client in the order they were sent, until the
+/// connection fails or the client is gone.
+fn start_writer(stream: &TcpStream) -> io::Result<Sender<Message>> {
+    let mut writer = stream.try_clone()?;
+    let (outbox, messages) = channel();
+
+    spawn(move ||{
+        for message in messages {
+            if write_message(&mut writer, &message).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(outbox)
+}
+
+fn handle_client(stream: TcpStream, lobby: Arc<Mutex<Lobby>>) -> io::Result<()> {
+    let mut reader = stream.try_clone()?;
+    let outbox = start_writer(&stream)?;
+    let id = {
+        let mut lobby = lobby.lock().unwrap();
+        lobby.next_id += 1;
+        lobby.next_id
+    };
+    let new_player = ||Ok::<_, io::Error>(Player {id, stream: stream.try_clone()?, outbox: outbox.clone()});
+    // The room this client plays in. Its mark is looked up by the id, a rematch swaps the seats.
+    let mut seat_in: Option<String> = None;
+    let mut said_bye = false;
+
+    while let Ok(message) = read_message(&mut reader) {
+        let mut lobby = lobby.lock().unwrap();
+
+        match message {
+            Message::List => {
+                let mut rooms: Vec<_> = lobby.rooms.iter()
+                    .filter(|(_, room)|room.is_waiting())
+                    .map(|(name, _)|name.clone())
+                    .collect();
+                rooms.sort();
+                send(&outbox, &Message::Rooms(rooms));
+            }
+            Message::Create(name) if seat_in.is_none() => {
+                if !is_valid_room(&name) {
+                    send(&outbox, &Message::Error(String::from("invalid room name")));
+                } else if lobby.rooms.contains_key(&name) {
+                    send(&outbox, &Message::Error(format!("the room {} already exists", name)));
+                } else {
+                    let player = new_player()?;
+                    lobby.rooms.insert(name.clone(), Room {
+                        position: Position::start(),
+                        history: Vec::new(),
+                        players: [Some(player), None],
+                        tokens: [new_token(), new_token()],
+                        started: false,
+                        ended: false,
+                        draw_offer: None,
+                        rematch_offer: None,
+                        spectators: Vec::new(),
+                        abandoned: None,
+                    });
+                    seat_in = Some(name);
+                }
+            }
+            Message::Join(name) if seat_in.is_none() => {
+                match lobby.rooms.get_mut(&name) {
+                    Some(room) if room.is_waiting() => {
+                        let mark = if room.players[0].is_none() {Mark::Cross} else {Mark::Circle};
+                        room.players[seat(mark)] = Some(new_player()?);
+                        room.started = true;
+
+                        for mark in [Mark::Cross, Mark::Circle] {
+                            let token = room.tokens[seat(mark)].clone();
+                            if let Some(player) = room.player(mark) {
+                                send(&player.outbox, &Message::Start(mark));
+                                send(&player.outbox, &Message::Session(token));
+                            }
+                        }
+                        seat_in = Some(name);
+                    }
+                    Some(_) => send(&outbox, &Message::Error(format!("the room {} is full", name))),
+                    None => send(&outbox, &Message::Error(format!("there is no room {}", name))),
+                }
+            }
+            Message::Watch(name) if seat_in.is_none() => {
+                match lobby.rooms.get_mut(&name) {
+                    Some(room) => {
+                        send(&outbox, &Message::History(room.history.clone()));
+                        room.spectators.push(new_player()?);
+                    }
+                    None => send(&outbox, &Message::Error(format!("there is no room {}", name))),
+                }
+            }
+            Message::Resume(token) if seat_in.is_none() => {
+                let found = lobby.rooms.iter_mut()
+                    .filter(|(_, room)|room.started)
+                    .find_map(|(name, room)|{
+                        let mark = [Mark::Cross, Mark::Circle].iter().copied()
+                            .find(|mark|room.tokens[seat(*mark)] == token)?;
+                        Some((name.clone(), room, mark))
+                    });
+
+                match found {
+                    Some((name, room, mark)) => {
+                        // The old connection may not have noticed yet that it is dead.
+                        if let Some(old) = room.player(mark) {
+                            let _ = old.stream.shutdown(Shutdown::Both);
+                        }
+                        room.players[seat(mark)] = Some(new_player()?);
+                        room.abandoned = None;
+                        send(&outbox, &Message::Resumed(mark, room.history.clone()));
+                        seat_in = Some(name);
+                    }
+                    None => send(&outbox, &Message::Error(String::from("the game of the session is over"))),
+                }
+            }
+            Message::Move(mv) => {
+                let room = seat_in.as_ref()
+                    .and_then(|name|lobby.rooms.get_mut(name))
+                    .and_then(|room|room.mark_of(id).map(|mark|(room, mark)));
+
+                match room {
+                    Some((room, _)) if room.ended => send(&outbox, &Message::Error(String::from("the game is over"))),
+                    Some((room, mark)) if !room.is_waiting() && room.position.next_turn == mark => {
+                        match room.position.play(mv) {
+                            Ok(()) => {
+                                room.history.push(mv);
+                                if room.draw_offer == Some(mark.other()) {
+                                    room.draw_offer = None;
+                                }
+                                room.relay(mark, &Message::Move(mv));
+                            }
+                            Err(err) => send(&outbox, &Message::Error(err.to_string())),
+                        }
+                    }
+                    Some(_) => send(&outbox, &Message::Error(format!("it is not your turn for {}", format_move(mv)))),
+                    None => send(&outbox, &Message::Error(String::from("you are not in a game"))),
+                }
+            }
+            Message::Action(action, _) => {
+                // The mark is taken from the seat, a player can only act for itself.
+                let room = seat_in.as_ref()
+                    .and_then(|name|lobby.rooms.get_mut(name))
+                    .and_then(|room|room.mark_of(id).map(|mark|(room, mark)));
+
+                match room {
+                    Some((room, mark)) if room.started => room.act(action, mark),
+                    Some(_) => send(&outbox, &Message::Error(String::from("the game did not start yet"))),
+                    None => send(&outbox, &Message::Error(String::from("you are not in a game"))),
+                }
+            }
+            Message::Bye => {
+                said_bye = true;
+                break;
+            }
+            _ => send(&outbox, &Message::Error(String::from("unexpected message"))),
+        }
+    }
+
+    let abandoned = {
+        let mut lobby = lobby.lock().unwrap();
+        for room in lobby.rooms.values_mut() {
+            room.spectators.retain(|spectator|spectator.id != id);
+        }
+        if said_bye {
+            leave(&mut lobby, seat_in, id);
+            None
+        } else {
+            step_away(&mut lobby, seat_in, id)
+        }
+    };
+    if let Some(name) = abandoned {
+        spawn(move ||{
+            sleep(ABANDONED_ROOM_TIME);
+            close_abandoned(&mut lobby.lock().unwrap(), &name);
+        });
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(())
+}
+
+/// Closes the room of a leaving client, the opponent is told about it.
+fn leave(lobby: &mut Lobby, seat_in: Option<String>, id: u64) {
+    if let Some(name) = seat_in {
+        let mark = lobby.rooms.get(&name).and_then(|room|room.mark_of(id));
+
+        if let Some(mark) = mark {
+            if let Some(mut room) = lobby.rooms.remove(&name) {
+                room.relay(mark, &Message::Bye);
+            }
+        }
+    }
+}
+
+/// Keeps the seat of a player who lost the connection, so it can resume the game.
+/// A room nobody can resume anymore is closed. Returns the name of the room if both
+/// players are away now, it is closed unless one of them resumes in time.
+fn step_away(lobby: &mut Lobby, seat_in: Option<String>, id: u64) -> Option<String> {
+    let name = seat_in?;
+    let resumable = lobby.rooms.get(&name)
+        .is_some_and(|room|room.started && !room.is_over());
+    if !resumable {
+        leave(lobby, Some(name), id);
+        return None;
+    }
+
+    let room = lobby.rooms.get_mut(&name).unwrap();
+    if let Some(mark) = room.mark_of(id) {
+        room.players[seat(mark)] = None;
+    }
+    if room.is_empty() {
+        room.abandoned = Some(Instant::now());
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Closes the room `name` if both players have been away for `ABANDONED_ROOM_TIME`.
+fn close_abandoned(lobby: &mut Lobby, name: &str) {
+    let expired = lobby.rooms.get(name)
+        .and_then(|room|room.abandoned)
+        .is_some_and(|since|since.elapsed() >= ABANDONED_ROOM_TIME);
+
+    if expired {
+        if let Some(room) = lobby.rooms.remove(name) {
+            for spectator in &room.spectators {
+                send(&spectator.outbox, &Message::Bye);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::parse_move;
+    use std::time::Duration;
+
+    fn start() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        spawn(move ||serve(listener));
+        address
+    }
+
+    fn connect(address: SocketAddr) -> TcpStream {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream
+    }
+
+    fn say(stream: &mut TcpStream, message: Message) {
+        write_message(stream, &message).unwrap();
+    }
+
+    fn hear(stream: &mut TcpStream) -> Message {
+        read_message(stream).unwrap()
+    }
+
+    #[test]
+    fn relays_moves_between_the_players() {
+        let address = start();
+
+        let mut host = connect(address);
+        say(&mut host, Message::Create(String::from("loopback")));
+        // The lobby answers in order, so the room exists once it is listed.
+        say(&mut host, Message::List);
+        assert_eq!(hear(&mut host), Message::Rooms(vec![String::from("loopback")]));
+
+        let mut guest = connect(address);
+        say(&mut guest, Message::Join(String::from("loopback")));
+        assert_eq!(hear(&mut host), Message::Start(Mark::Cross));
+        assert!(matches!(hear(&mut host), Message::Session(_)));
+        assert_eq!(hear(&mut guest), Message::Start(Mark::Circle));
+        assert!(matches!(hear(&mut guest), Message::Session(_)));
+
+        let mv = parse_move("55").unwrap();
+        say(&mut host, Message::Move(mv));
+        assert_eq!(hear(&mut guest), Message::Move(mv));
+
+        // The guest has to answer in field 5.
+        say(&mut guest, Message::Move(parse_move("11").unwrap()));
+        assert!(matches!(hear(&mut guest), Message::Error(_)));
+
+        let reply = parse_move("51").unwrap();
+        say(&mut guest, Message::Move(reply));
+        assert_eq!(hear(&mut host), Message::Move(reply));
+    }
+}
@@ -0,0 +1,276 @@
+//! The preferences of the app, kept in a file in the config directory of the user and
+//! loaded at the start.
+
+use crate::data::{GameData, Mark, StartOpponent, Rules};
+use crate::ai::{MIN_LEVEL, MAX_LEVEL};
+use crate::network::protocol::{mark_from_str, mark_to_str};
+use crate::theme::Theme;
+use druid::Size;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The longest delay the AI may wait before its move, in milliseconds.
+pub const MAX_AI_DELAY: u64 = 5000;
+
+/// The size of the window at the first start.
+pub const DEFAULT_WINDOW_SIZE: Size = Size::new(700.0, 1000.0);
+
+/// The settings kept between the starts of the app.
+///
+/// The file format is line based: `<key> = <value>`, lines starting with `#` are
+/// comments. Unknown keys and invalid values are ignored, so older files still work.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub opponent: StartOpponent,
+    pub ai_mark: Mark,
+    pub cross_level: u64,
+    pub circle_level: u64,
+    pub ai_delay: u64,
+    pub engine_command: String,
+    pub theme: Theme,
+    pub animations: bool,
+    pub rules: Rules,
+    pub window_size: Size,
+}
+
+impl Settings {
+    /// The file of the settings, `TICKTACKTOE_SETTINGS` overrides the config directory.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var_os("TICKTACKTOE_SETTINGS") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => dirs::config_dir().map(|dir|dir.join("ticktacktoe_ultimate").join("settings.txt")),
+        }
+    }
+
+    /// The saved settings, the defaults if there are none yet or they can't be read.
+    pub fn load() -> Self {
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return Settings::default(),
+        };
+
+        match Settings::read(&path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    eprintln!("could not load settings {}: {}", path.display(), err);
+                }
+                Settings::default()
+            }
+        }
+    }
+
+    /// Writes the settings to `path`, returns where they went.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = Settings::path()
+            .ok_or_else(||io::Error::new(ErrorKind::NotFound, "there is no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        writeln!(file, "# Tick Tack Toe Ultimate settings")?;
+        writeln!(file, "opponent = {}", opponent_to_str(self.opponent))?;
+        writeln!(file, "ai_mark = {}", mark_to_str(self.ai_mark))?;
+        writeln!(file, "cross_level = {}", self.cross_level)?;
+        writeln!(file, "circle_level = {}", self.circle_level)?;
+        writeln!(file, "ai_delay = {}", self.ai_delay)?;
+        writeln!(file, "engine_command = {}", self.engine_command)?;
+        writeln!(file, "theme = {}", theme_to_str(self.theme))?;
+        writeln!(file, "animations = {}", self.animations)?;
+        writeln!(file, "rules = {}", rules_to_str(self.rules))?;
+        writeln!(file, "window_width = {}", self.window_size.width)?;
+        writeln!(file, "window_height = {}", self.window_size.height)?;
+        file.flush()?;
+
+        Ok(path)
+    }
+
+    fn read(path: &Path) -> io::Result<Self> {
+        let mut settings = Settings::default();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                settings.set(key.trim(), value.trim());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// Sets the setting `key` to `value`, if both are valid.
+    fn set(&mut self, key: &str, value: &str) {
+        let level = ||value.parse().ok().filter(|level|(MIN_LEVEL..=MAX_LEVEL).contains(level));
+        let length = ||value.parse().ok().filter(|length: &f64|length.is_finite() && *length > 0.0);
+
+        match key {
+            "opponent" => if let Some(opponent) = opponent_from_str(value) {
+                self.opponent = opponent;
+            },
+            "ai_mark" => if let Some(mark) = mark_from_str(value) {
+                self.ai_mark = mark;
+            },
+            "cross_level" => if let Some(level) = level() {
+                self.cross_level = level;
+            },
+            "circle_level" => if let Some(level) = level() {
+                self.circle_level = level;
+            },
+            "ai_delay" => if let Some(delay) = value.parse().ok().filter(|delay|*delay <= MAX_AI_DELAY) {
+                self.ai_delay = delay;
+            },
+            "engine_command" => self.engine_command = String::from(value),
+            "theme" => if let Some(theme) = theme_from_str(value) {
+                self.theme = theme;
+            },
+            "animations" => if let Ok(animations) = value.parse() {
+                self.animations = animations;
+            },
+            "rules" => if let Some(rules) = rules_from_str(value) {
+                self.rules = rules;
+            },
+            "window_width" => if let Some(width) = length() {
+                self.window_size.width = width;
+            },
+            "window_height" => if let Some(height) = length() {
+                self.window_size.height = height;
+            },
+            _ => {}
+        }
+    }
+
+    /// The current settings of the app.
+    pub fn from_data(data: &GameData) -> Self {
+        Settings {
+            opponent: data.start_opponent,
+            ai_mark: data.ai_mark,
+            cross_level: data.cross_level,
+            circle_level: data.circle_level,
+            ai_delay: data.ai_delay,
+            engine_command: data.engine_command.clone(),
+            theme: data.theme,
+            animations: data.animations,
+            rules: data.rules,
+            window_size: data.window_size,
+        }
+    }
+
+    /// Uses the settings for `data`, the game is not restarted.
+    pub fn apply(&self, data: &mut GameData) {
+        data.start_opponent = self.opponent;
+        data.ai_mark = self.ai_mark;
+        data.cross_level = self.cross_level;
+        data.circle_level = self.circle_level;
+        data.ai_delay = self.ai_delay;
+        data.engine_command = self.engine_command.clone();
+        data.theme = self.theme;
+        data.animations = self.animations;
+        data.rules = self.rules;
+        data.window_size = self.window_size;
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::from_data(&GameData::local(DEFAULT_WINDOW_SIZE))
+    }
+}
+
+fn opponent_to_str(opponent: StartOpponent) -> &'static str {
+    match opponent {
+        StartOpponent::TwoPlayers => "two-players",
+        StartOpponent::Ai => "ai",
+        StartOpponent::Engine => "engine",
+    }
+}
+
+fn opponent_from_str(text: &str) -> Option<StartOpponent> {
+    match text {
+        "two-players" => Some(StartOpponent::TwoPlayers),
+        "ai" => Some(StartOpponent::Ai),
+        "engine" => Some(StartOpponent::Engine),
+        _ => None,
+    }
+}
+
+fn theme_to_str(theme: Theme) -> &'static str {
+    match theme {
+        Theme::Classic => "classic",
+        Theme::Dark => "dark",
+        Theme::Light => "light",
+        Theme::ColorBlind => "color-blind",
+    }
+}
+
+fn theme_from_str(text: &str) -> Option<Theme> {
+    match text {
+        "classic" => Some(Theme::Classic),
+        "dark" => Some(Theme::Dark),
+        "light" => Some(Theme::Light),
+        "color-blind" => Some(Theme::ColorBlind),
+        _ => None,
+    }
+}
+
+fn rules_to_str(rules: Rules) -> &'static str {
+    match rules {
+        Rules::Standard => "standard",
+        Rules::OpenFields => "open-fields",
+    }
+}
+
+fn rules_from_str(text: &str) -> Option<Rules> {
+    match text {
+        "standard" => Some(Rules::Standard),
+        "open-fields" => Some(Rules::OpenFields),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_the_settings() {
+        let path = std::env::temp_dir().join(format!("ticktacktoe_settings_{}.txt", std::process::id()));
+        std::env::set_var("TICKTACKTOE_SETTINGS", &path);
+
+        let settings = Settings {
+            opponent: StartOpponent::Ai,
+            ai_mark: Mark::Cross,
+            cross_level: MIN_LEVEL,
+            circle_level: MAX_LEVEL,
+            ai_delay: 250,
+            engine_command: String::from("engine --depth 4"),
+            theme: Theme::ColorBlind,
+            animations: false,
+            rules: Rules::OpenFields,
+            window_size: Size::new(800.0, 900.0),
+        };
+        assert_eq!(settings.save().unwrap(), path);
+        assert_eq!(Settings::load(), settings);
+
+        fs::write(&path, "\
+# An older file
+cross_level = 99
+ai_delay = -3
+theme = neon
+rules = none
+window_width = 0
+window_height = inf
+unknown = 1
+no value
+animations = false
+").unwrap();
+        let loaded = Settings::load();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Settings {animations: false, ..Settings::default()});
+    }
+}
@@ -0,0 +1,189 @@
+//! Draws positions as SVG images, looking like the board of the app.
+
+use crate::data::{Position, Mark, Move, FieldPosition, Slot, parse_move};
+use std::fmt::Write;
+
+/// The size of a small field, its largest size in the app.
+const FIELD_SIZE: f64 = 250.0;
+/// The space between the small fields.
+const SPACING: f64 = 60.0;
+/// The space around the board, the active fields and the won marks reach into it.
+const MARGIN: f64 = 20.0;
+/// How far the highlight of the active fields and the overlay of won fields reach
+/// beyond the field.
+const HIGHLIGHT_GROWTH: f64 = 20.0;
+
+/// The colors of the default druid theme.
+const WINDOW_BACKGROUND: &str = "#292929";
+const BACKGROUND_LIGHT: &str = "#3a3a3a";
+const CROSS: &str = "#ff0000";
+const CIRCLE: &str = "#0000ff";
+const LAST_MOVE: &str = "#ffd700";
+const ARROW: &str = "#3cb371";
+
+/// An arrow from the slot of one move to the slot of another.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Arrow {
+    pub from: Move,
+    pub to: Move,
+}
+
+/// Parses an arrow written as two moves joined by `-`, e.g. `"59-95"`.
+pub fn parse_arrow(text: &str) -> Option<Arrow> {
+    let (from, to) = text.split_once('-')?;
+    Some(Arrow {
+        from: parse_move(from)?,
+        to: parse_move(to)?,
+    })
+}
+
+/// What is drawn on top of the position.
+#[derive(Clone, Default, Debug)]
+pub struct Annotations {
+    pub last_move: Option<Move>,
+    pub arrows: Vec<Arrow>,
+}
+
+pub fn board_size() -> f64 {
+    MARGIN * 2.0 + FIELD_SIZE * 3.0 + SPACING * 2.0
+}
+
+/// Renders `position` with the fields the next move may go to highlighted.
+pub fn render(position: &Position, annotations: &Annotations) -> String {
+    let size = board_size();
+    let mut svg = String::new();
+
+    // Writing to a `String` can't fail.
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#, size);
+    let _ = writeln!(
+        svg,
+        r#"<defs><marker id="arrowhead" viewBox="0 0 10 10" refX="5" refY="5" markerWidth="3" markerHeight="3" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z" fill="{}"/></marker></defs>"#,
+        ARROW,
+    );
+    let _ = writeln!(svg, r#"<rect width="{0}" height="{0}" fill="{1}"/>"#, size, WINDOW_BACKGROUND);
+
+    for outer in FieldPosition::all() {
+        let active = !position.is_finished() &&
+            position.next_field.is_none_or(|next|next == outer) &&
+            position.game[outer].has_free();
+
+        draw_field(&mut svg, position, outer, active, annotations.last_move);
+    }
+    for arrow in &annotations.arrows {
+        draw_arrow(&mut svg, *arrow);
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The top left corner of a small field.
+fn field_origin(outer: FieldPosition) -> (f64, f64) {
+    (
+        MARGIN + outer.x() as f64 * (FIELD_SIZE + SPACING),
+        MARGIN + outer.y() as f64 * (FIELD_SIZE + SPACING),
+    )
+}
+
+/// The center of the slot of a move.
+fn slot_center((outer, inner): Move) -> (f64, f64) {
+    let (x, y) = field_origin(outer);
+    let slot_size = FIELD_SIZE / 3.0;
+
+    (x + (inner.x() as f64 + 0.5) * slot_size, y + (inner.y() as f64 + 0.5) * slot_size)
+}
+
+fn draw_field(svg: &mut String, position: &Position, outer: FieldPosition, active: bool, last_move: Option<Move>) {
+    let field = position.game[outer];
+    let (x0, y0) = field_origin(outer);
+    let grid_width = FIELD_SIZE / 90.0;
+    let mark_width = FIELD_SIZE / 30.0;
+    let slot_size = FIELD_SIZE / 3.0;
+
+    if active {
+        rounded_rect(svg, inset((x0, y0, x0 + FIELD_SIZE, y0 + FIELD_SIZE), -HIGHLIGHT_GROWTH), slot_size / 3.0, BACKGROUND_LIGHT, 1.0);
+    }
+
+    for line in 1..3 {
+        let offset = slot_size * line as f64;
+        let _ = writeln!(
+            svg,
+            r#"<path d="M {:.2} {:.2} H {:.2} M {:.2} {:.2} V {:.2}" stroke="black" stroke-width="{:.2}"/>"#,
+            x0, y0 + offset, x0 + FIELD_SIZE,
+            x0 + offset, y0, y0 + FIELD_SIZE,
+            grid_width,
+        );
+    }
+
+    for inner in FieldPosition::all() {
+        let slot = (
+            x0 + inner.x() as f64 * slot_size,
+            y0 + inner.y() as f64 * slot_size,
+            x0 + (inner.x() + 1) as f64 * slot_size,
+            y0 + (inner.y() + 1) as f64 * slot_size,
+        );
+
+        if last_move == Some((outer, inner)) {
+            let (x0, y0, x1, y1) = inset(slot, mark_width / 2.0);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" rx="{:.2}" fill="none" stroke="{}" stroke-width="{:.2}"/>"#,
+                x0, y0, x1 - x0, y1 - y0, slot_size / 6.0, LAST_MOVE, grid_width * 2.0,
+            );
+        }
+        if let Some(mark) = field[inner] {
+            draw_mark(svg, inset(slot, mark_width * 2.0), mark_width, mark);
+        }
+    }
+
+    if let Some(mark) = field.belongs_to() {
+        rounded_rect(svg, inset((x0, y0, x0 + FIELD_SIZE, y0 + FIELD_SIZE), -HIGHLIGHT_GROWTH), slot_size / 3.0, WINDOW_BACKGROUND, 0.7);
+
+        let bounds = inset((x0, y0, x0 + FIELD_SIZE, y0 + FIELD_SIZE), grid_width);
+        let line_width = (bounds.2 - bounds.0) / 10.0;
+        draw_mark(svg, inset(bounds, line_width), line_width, mark);
+    }
+}
+
+/// Draws `mark` inside `bounds` like `ui::field::draw_mark`.
+fn draw_mark(svg: &mut String, (x0, y0, x1, y1): (f64, f64, f64, f64), line_width: f64, mark: Mark) {
+    match mark {
+        Mark::Cross => {
+            let _ = writeln!(
+                svg,
+                r#"<path d="M {:.2} {:.2} L {:.2} {:.2} M {:.2} {:.2} L {:.2} {:.2}" stroke="{}" stroke-width="{:.2}" stroke-linecap="round"/>"#,
+                x0, y0, x1, y1, x0, y1, x1, y0, CROSS, line_width,
+            );
+        }
+        Mark::Circle => {
+            let _ = writeln!(
+                svg,
+                r#"<ellipse cx="{:.2}" cy="{:.2}" rx="{:.2}" ry="{:.2}" fill="none" stroke="{}" stroke-width="{:.2}"/>"#,
+                (x0 + x1) / 2.0, (y0 + y1) / 2.0, (x1 - x0) / 2.0, (y1 - y0) / 2.0, CIRCLE, line_width,
+            );
+        }
+    }
+}
+
+fn draw_arrow(svg: &mut String, arrow: Arrow) {
+    let (x0, y0) = slot_center(arrow.from);
+    let (x1, y1) = slot_center(arrow.to);
+
+    let _ = writeln!(
+        svg,
+        r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-width="{:.2}" stroke-linecap="round" stroke-opacity="0.8" marker-end="url(#arrowhead)"/>"#,
+        x0, y0, x1, y1, ARROW, FIELD_SIZE / 25.0,
+    );
+}
+
+fn rounded_rect(svg: &mut String, (x0, y0, x1, y1): (f64, f64, f64, f64), radius: f64, color: &str, opacity: f64) {
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" rx="{:.2}" fill="{}" fill-opacity="{}"/>"#,
+        x0, y0, x1 - x0, y1 - y0, radius, color, opacity,
+    );
+}
+
+fn inset((x0, y0, x1, y1): (f64, f64, f64, f64), by: f64) -> (f64, f64, f64, f64) {
+    (x0 + by, y0 + by, x1 - by, y1 - by)
+}
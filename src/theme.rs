@@ -0,0 +1,25 @@
+//! The color themes of the app, `ui::theme` sets their colors.
+
+use druid::Data;
+
+/// The colors of the board and the rest of the window.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Data)]
+pub enum Theme {
+    /// The colors of the druid theme.
+    Classic,
+    Dark,
+    Light,
+    /// Orange and blue, which can be told apart with the common kinds of color blindness.
+    ColorBlind,
+}
+
+impl Theme {
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+            Theme::ColorBlind => "Color blind",
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Plays in the terminal, for example over SSH.
+//!
+//! The cursor is moved with the arrow keys or `hjkl` and a move is made with enter or
+//! space. A move can also be typed as two digits, the field and the slot in it, see
+//! `parse_move`. `n` starts a new game and `q` quits.
+
+use crate::data::{Position, Mark, Move, FieldPosition, Slot, DEFAULT_LEVEL, format_move};
+use crate::ai::{EngineSettings, instant_move, calc_move};
+use crate::network::protocol::mark_from_str;
+use crossterm::{queue, execute};
+use crossterm::cursor::{MoveTo, Hide, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Print, SetForegroundColor, SetBackgroundColor, ResetColor, SetAttribute, Attribute, Color};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen, Clear, ClearType};
+use std::io::{self, Stdout, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::spawn;
+use std::time::Duration;
+
+/// How long to wait for a key before looking for the move of the AI again.
+const POLL_TIME: Duration = Duration::from_millis(50);
+
+/// `ai` is the mark the AI plays, `x`, `o` or `none` for two players at the keyboard.
+pub fn run(ai: Option<String>, level: Option<u64>) {
+    let ai = match ai.as_deref() {
+        Some("none") => None,
+        Some(text) => mark_from_str(text).or(Some(Mark::Circle)),
+        None => Some(Mark::Circle),
+    };
+    let game = Game::new(ai, level.unwrap_or(DEFAULT_LEVEL));
+
+    let mut stdout = io::stdout();
+    let result = Terminal::enter(&mut stdout).and_then(|_terminal|game.play(&mut stdout));
+
+    if let Err(err) = result {
+        eprintln!("terminal failed: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Switches the terminal to the game screen and back when dropped.
+struct Terminal;
+
+impl Terminal {
+    fn enter(stdout: &mut Stdout) -> io::Result<Terminal> {
+        terminal::enable_raw_mode().map_err(to_io)?;
+        execute!(stdout, EnterAlternateScreen, Hide).map_err(to_io)?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn to_io(err: crossterm::ErrorKind) -> io::Error {
+    match err {
+        crossterm::ErrorKind::IoError(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
+
+struct Game {
+    position: Position,
+    ai: Option<Mark>,
+    level: u64,
+    /// The slot under the cursor, column and row of the 9×9 board.
+    cursor: (usize, usize),
+    /// The first digit of a typed move.
+    typed: Option<FieldPosition>,
+    last_move: Option<Move>,
+    message: String,
+    thinking: Option<Receiver<Move>>,
+}
+
+impl Game {
+    fn new(ai: Option<Mark>, level: u64) -> Self {
+        Game {
+            position: Position::start(),
+            ai,
+            level,
+            cursor: (4, 4),
+            typed: None,
+            last_move: None,
+            message: String::new(),
+            thinking: None,
+        }
+    }
+
+    fn play(mut self, stdout: &mut Stdout) -> io::Result<()> {
+        let mut changed = true;
+
+        loop {
+            changed |= self.think();
+            if changed {
+                self.draw(stdout)?;
+                changed = false;
+            }
+
+            if event::poll(POLL_TIME).map_err(to_io)? {
+                match event::read().map_err(to_io)? {
+                    Event::Key(key) => if !self.key(key) {
+                        return Ok(());
+                    },
+                    Event::Resize(..) => {}
+                    Event::Mouse(_) => continue,
+                }
+                changed = true;
+            }
+        }
+    }
+
+    /// Lets the AI search its move in the background and plays it once it is found.
+    /// Returns whether something changed.
+    fn think(&mut self) -> bool {
+        if let Some(thinking) = &self.thinking {
+            if let Ok(mv) = thinking.try_recv() {
+                self.thinking = None;
+                self.make_move(mv);
+                return true;
+            }
+            return false;
+        }
+        if self.position.is_finished() || self.ai != Some(self.position.next_turn) {
+            return false;
+        }
+
+        let position = self.position;
+        let settings = EngineSettings::preset(self.level);
+        let (sender, receiver) = channel();
+        spawn(move ||{
+            let mv = instant_move(position.game, position.next_turn, position.next_field, &settings)
+                .unwrap_or_else(||calc_move(position.game, position.next_turn, position.next_field, &settings));
+            let _ = sender.send(mv);
+        });
+        self.thinking = Some(receiver);
+        self.message = String::from("The AI is thinking");
+        true
+    }
+
+    fn make_move(&mut self, mv: Move) {
+        match self.position.play(mv) {
+            Ok(()) => {
+                self.last_move = Some(mv);
+                self.message = format!("{} played {}", self.position.next_turn.other(), format_move(mv));
+            }
+            Err(err) => self.message = err.to_string(),
+        }
+    }
+
+    /// Handles a key, returns `false` to quit.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let (x, y) = self.cursor;
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('n') => {
+                let (ai, level) = (self.ai, self.level);
+                *self = Game::new(ai, level);
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.cursor = ((x + 8) % 9, y),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor = ((x + 1) % 9, y),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = (x, (y + 8) % 9),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (x, (y + 1) % 9),
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let outer = FieldPosition::from((x / 3, y / 3));
+                let inner = FieldPosition::from((x % 3, y % 3));
+                self.human_move((outer, inner));
+            }
+            KeyCode::Char(c @ '1'..='9') => {
+                let position = FieldPosition::from_index(c as usize - '1' as usize).unwrap();
+                match self.typed.take() {
+                    Some(outer) => self.human_move((outer, position)),
+                    None => {
+                        self.typed = Some(position);
+                        self.message = format!("Field {}, now type the slot", position);
+                    }
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn human_move(&mut self, mv: Move) {
+        if self.thinking.is_some() || self.ai == Some(self.position.next_turn) {
+            self.message = String::from("Wait for the AI to move");
+        } else if !self.position.is_legal(mv) {
+            self.message = format!("{} is not possible", format_move(mv));
+        } else {
+            let (outer, inner) = mv;
+            self.cursor = (outer.x() * 3 + inner.x(), outer.y() * 3 + inner.y());
+            self.make_move(mv);
+        }
+    }
+
+    /// Whether the next move may go to `outer`.
+    fn is_playable(&self, outer: FieldPosition) -> bool {
+        !self.position.is_finished() &&
+            self.position.next_field.is_none_or(|next|next == outer) &&
+            self.position.game[outer].has_free()
+    }
+
+    fn draw(&self, stdout: &mut Stdout) -> io::Result<()> {
+        queue!(stdout, Clear(ClearType::All), MoveTo(2, 1)).map_err(to_io)?;
+
+        for y in 0..9 {
+            if y > 0 && y % 3 == 0 {
+                queue!(stdout, MoveTo(2, 1 + row_line(y) - 1), Print("─────────┼─────────┼─────────")).map_err(to_io)?;
+            }
+            queue!(stdout, MoveTo(2, 1 + row_line(y))).map_err(to_io)?;
+
+            for x in 0..9 {
+                if x > 0 && x % 3 == 0 {
+                    queue!(stdout, ResetColor, Print("│")).map_err(to_io)?;
+                }
+                self.draw_slot(stdout, x, y)?;
+            }
+            queue!(stdout, ResetColor).map_err(to_io)?;
+        }
+
+        let status = match self.position.game.belongs_to() {
+            Some(mark) => format!("{} won the game! n: new game, q: quit", mark),
+            None if self.position.is_finished() => String::from("Draw! n: new game, q: quit"),
+            None => format!("{}'s turn", self.position.next_turn),
+        };
+        queue!(
+            stdout,
+            MoveTo(2, 14), Print(status),
+            MoveTo(2, 15), Print(&self.message),
+            MoveTo(2, 17), Print("arrows/hjkl: move, enter: play, 1-9 1-9: type a move, n: new game, q: quit"),
+        ).map_err(to_io)?;
+
+        stdout.flush()
+    }
+
+    fn draw_slot(&self, stdout: &mut Stdout, x: usize, y: usize) -> io::Result<()> {
+        let outer = FieldPosition::from((x / 3, y / 3));
+        let inner = FieldPosition::from((x % 3, y % 3));
+        let field = self.position.game[outer];
+
+        let background = if self.cursor == (x, y) {
+            Color::Grey
+        } else if self.is_playable(outer) {
+            Color::DarkGreen
+        } else {
+            Color::Reset
+        };
+        // A won field is painted in the color of its winner.
+        let foreground = match field.belongs_to().or(field[inner]) {
+            Some(Mark::Cross) => Color::Red,
+            Some(Mark::Circle) => Color::Blue,
+            None => Color::DarkGrey,
+        };
+        let text = match field[inner] {
+            Some(Mark::Cross) => " x ",
+            Some(Mark::Circle) => " o ",
+            None => " · ",
+        };
+
+        queue!(stdout, SetBackgroundColor(background), SetForegroundColor(foreground)).map_err(to_io)?;
+        if self.last_move == Some((outer, inner)) {
+            queue!(stdout, SetAttribute(Attribute::Bold), Print(text), SetAttribute(Attribute::NormalIntensity)).map_err(to_io)?;
+        } else {
+            queue!(stdout, Print(text)).map_err(to_io)?;
+        }
+        Ok(())
+    }
+}
+
+/// The screen line of a row of slots, leaving space for the lines between the fields.
+fn row_line(y: usize) -> u16 {
+    (y + y / 3) as u16
+}
@@ -1,24 +1,114 @@
-use crate::data::{Mark, FieldMeta, Slot};
-use druid::{Widget, LifeCycle, EventCtx, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, RenderContext, Color, Rect};
+use crate::data::{Mark, FieldMeta, FieldPosition, Slot, GameData};
+use crate::ui::theme::{mark_color, GRID_COLOR, ACTIVE_FIELD_COLOR, WON_FIELD_COLOR, LAST_MOVE_COLOR};
+use druid::{Widget, WidgetPod, LifeCycle, EventCtx, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, RenderContext, Rect, Color, Point, Vec2};
 use druid::piet::{StrokeStyle, LineCap};
-use druid::kurbo::Line;
+use druid::kurbo::{Line, Arc};
+use std::f64::consts::PI;
+
+/// The space between the small fields of the board.
+pub const FIELD_SPACING: f64 = 60.0;
+
+/// How long the animations take, in seconds.
+const PLACING_TIME: f64 = 0.2;
+const WINNING_TIME: f64 = 0.4;
+const HIGHLIGHT_TIME: f64 = 0.3;
+
+/// An animation running from 0 to 1.
+#[derive(Copy, Clone)]
+struct Progress {
+    value: f64,
+    duration: f64,
+}
+
+impl Progress {
+    fn done(duration: f64) -> Self {
+        Progress {
+            value: 1.0,
+            duration,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.value = 0.0;
+    }
+
+    fn finish(&mut self) {
+        self.value = 1.0;
+    }
+
+    fn advance(&mut self, seconds: f64) {
+        self.value = (self.value + seconds / self.duration).min(1.0);
+    }
+
+    fn is_running(&self) -> bool {
+        self.value < 1.0
+    }
+
+    /// The progress slowing down towards the end.
+    fn eased(&self) -> f64 {
+        1.0 - (1.0 - self.value).powi(3)
+    }
+}
+
+/// `color` with its alpha scaled by `factor`.
+fn faded(color: Color, factor: f64) -> Color {
+    let (r, g, b, a) = color.as_rgba();
+    Color::rgba(r, g, b, a * factor)
+}
+
+/// The bounds of the slot at `index`.
+fn slot_rect(index: FieldPosition, slot_size: f64) -> Rect {
+    Rect::new(
+        index.x() as f64 * slot_size,
+        index.y() as f64 * slot_size,
+        (index.x() + 1) as f64 * slot_size,
+        (index.y() + 1) as f64 * slot_size,
+    )
+}
+
+/// The direction of a line of three positions, one step per position.
+fn direction(line: [FieldPosition; 3]) -> (f64, f64) {
+    (
+        (line[2].x() as f64 - line[0].x() as f64) / 2.0,
+        (line[2].y() as f64 - line[0].y() as f64) / 2.0,
+    )
+}
+
+pub fn draw_mark(ctx: &mut PaintCtx, env: &Env, bounds: Rect, line_width: f64, alpha: f64, mark: Mark) {
+    draw_mark_partly(ctx, env, bounds, line_width, alpha, mark, 1.0);
+}
+
+/// Draws the part `progress` of `mark`, as if it was drawn with a pen.
+fn draw_mark_partly(ctx: &mut PaintCtx, env: &Env, bounds: Rect, line_width: f64, alpha: f64, mark: Mark, progress: f64) {
+    let brush = ctx.solid_brush(mark_color(env, mark).with_alpha(alpha));
+    let mut stroke_style = StrokeStyle::new();
+    stroke_style.line_cap = Some(LineCap::Round);
 
-pub fn draw_mark(ctx: &mut PaintCtx, bounds: Rect, line_width: f64, alpha: f64, mark: Mark) {
     match mark {
         Mark::Cross => {
-            let brush = ctx.solid_brush(Color::RED.with_alpha(alpha));
+            // One line after the other.
+            let first = (progress * 2.0).min(1.0);
+            let second = (progress * 2.0 - 1.0).max(0.0);
+            let (top_left, bottom_right) = (Point::new(bounds.x0, bounds.y0), Point::new(bounds.x1, bounds.y1));
+            let (bottom_left, top_right) = (Point::new(bounds.x0, bounds.y1), Point::new(bounds.x1, bounds.y0));
 
-            let mut stroke_style = StrokeStyle::new();
-            stroke_style.line_cap = Some(LineCap::Round);
-
-            ctx.stroke_styled(Line::new((bounds.x0, bounds.y0), (bounds.x1, bounds.y1)), &brush, line_width, &stroke_style);
-            ctx.stroke_styled(Line::new((bounds.x0, bounds.y1), (bounds.x1, bounds.y0)), &brush, line_width, &stroke_style);
+            ctx.stroke_styled(Line::new(top_left, top_left.lerp(bottom_right, first)), &brush, line_width, &stroke_style);
+            if second > 0.0 {
+                ctx.stroke_styled(Line::new(bottom_left, bottom_left.lerp(top_right, second)), &brush, line_width, &stroke_style);
+            }
+        }
+        Mark::Circle if progress < 1.0 => {
+            let arc = Arc {
+                center: bounds.center(),
+                radii: Vec2::new(bounds.width() / 2.0, bounds.height() / 2.0),
+                start_angle: -PI / 2.0,
+                sweep_angle: 2.0 * PI * progress,
+                x_rotation: 0.0,
+            };
+            ctx.stroke_styled(arc, &brush, line_width, &stroke_style);
         }
         Mark::Circle => {
-            let brush = ctx.solid_brush(Color::BLUE.with_alpha(alpha));
-
             ctx.stroke(bounds.to_ellipse(), &brush, line_width);
-
         }
     };
 }
@@ -26,6 +116,11 @@ pub fn draw_mark(ctx: &mut PaintCtx, bounds: Rect, line_width: f64, alpha: f64,
 pub struct FieldWidget {
     won: Option<Mark>,
     hover: Option<(usize, usize)>,
+    /// The slot of the mark being drawn.
+    placed: Option<FieldPosition>,
+    placing: Progress,
+    /// The overlay of the won field fading in.
+    winning: Progress,
 }
 
 impl FieldWidget {
@@ -33,10 +128,17 @@ impl FieldWidget {
         FieldWidget {
             hover: None,
             won: None,
+            placed: None,
+            placing: Progress::done(PLACING_TIME),
+            winning: Progress::done(WINNING_TIME),
         }
     }
 
-    pub fn draw_mark(&self, ctx: &mut PaintCtx, index: (usize, usize), mark: Mark, preview: bool) {
+    fn is_animating(&self) -> bool {
+        self.placing.is_running() || self.winning.is_running()
+    }
+
+    pub fn draw_mark(&self, ctx: &mut PaintCtx, env: &Env, index: (usize, usize), mark: Mark, preview: bool) {
         let line_width = ctx.size().width / 30.0 as f64;
         let slot_size = ctx.size().width / 3.0 as f64;
 
@@ -52,8 +154,13 @@ impl FieldWidget {
         } else {
             1.0
         };
+        let progress = if self.placed == Some(FieldPosition::from(index)) {
+            self.placing.eased()
+        } else {
+            1.0
+        };
 
-        draw_mark(ctx, bounds, line_width, alpha, mark);
+        draw_mark_partly(ctx, env, bounds, line_width, alpha, mark, progress);
     }
 }
 
@@ -83,6 +190,16 @@ impl Widget<FieldMeta> for FieldWidget {
                     ctx.request_paint();
                 }
             }
+            Event::AnimFrame(interval) => {
+                let seconds = *interval as f64 / 1_000_000_000.0;
+                self.placing.advance(seconds);
+                self.winning.advance(seconds);
+
+                ctx.request_paint();
+                if self.is_animating() {
+                    ctx.request_anim_frame();
+                }
+            }
             _ => {}
         }
     }
@@ -97,13 +214,32 @@ impl Widget<FieldMeta> for FieldWidget {
         }
     }
 
-    fn update(&mut self, ctx: &mut UpdateCtx, _: &FieldMeta, data: &FieldMeta, _: &Env) {
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &FieldMeta, data: &FieldMeta, _: &Env) {
         ctx.request_paint();
         self.won = data.belongs_to();
+
+        if !data.animate() {
+            self.placing.finish();
+            self.winning.finish();
+            return;
+        }
+        if let Some(index) = data.last_move() {
+            if old_data[index].is_none() && data[index].is_some() {
+                self.placed = Some(index);
+                self.placing.restart();
+            }
+        }
+        if old_data.belongs_to().is_none() && data.belongs_to().is_some() {
+            self.winning.restart();
+        }
+        if self.is_animating() {
+            ctx.request_anim_frame();
+        }
     }
 
     fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &FieldMeta, _env: &Env) -> Size {
-        ctx.set_paint_insets(20.0);
+        // The line through the won fields reaches halfway to the next field.
+        ctx.set_paint_insets(FIELD_SPACING / 2.0 + 10.0);
         bc.constrain_aspect_ratio(1.0, 250.0)
     }
 
@@ -112,53 +248,218 @@ impl Widget<FieldMeta> for FieldWidget {
         let line_width = ctx.size().width / 90.0;
         let slot_size = ctx.size().width / 3.0;
 
-        if data.is_active() {
-            let shape = ctx.size()
-                .to_rect()
-                .inset(20.0)
-                .to_rounded_rect(slot_size / 3.0);
-            let brush = ctx.solid_brush(env.get(druid::theme::BACKGROUND_LIGHT));
+        if let Some(index) = data.last_move() {
+            let shape = slot_rect(index, slot_size)
+                .inset(-line_width * 2.0)
+                .to_rounded_rect(slot_size / 6.0);
+            let brush = ctx.solid_brush(env.get(LAST_MOVE_COLOR));
 
             ctx.fill(shape, &brush);
         }
 
-        let black = ctx.solid_brush(Color::BLACK);
+        let grid = ctx.solid_brush(env.get(GRID_COLOR));
 
         //Vertical
-        ctx.stroke(Line::new((0.0, slot_size), (size, slot_size)), &black, line_width);
-        ctx.stroke(Line::new((0.0, slot_size * 2.0), (size, slot_size * 2.0)), &black, line_width);
+        ctx.stroke(Line::new((0.0, slot_size), (size, slot_size)), &grid, line_width);
+        ctx.stroke(Line::new((0.0, slot_size * 2.0), (size, slot_size * 2.0)), &grid, line_width);
 
         //Horizontal
-        ctx.stroke(Line::new((slot_size, 0.0), (slot_size, size)), &black, line_width);
-        ctx.stroke(Line::new((slot_size * 2.0, 0.0), (slot_size * 2.0, size)), &black, line_width);
+        ctx.stroke(Line::new((slot_size, 0.0), (slot_size, size)), &grid, line_width);
+        ctx.stroke(Line::new((slot_size * 2.0, 0.0), (slot_size * 2.0, size)), &grid, line_width);
 
         if let Some(index) = self.hover {
             if data[index].is_none() && data.is_active() {
-                self.draw_mark(ctx, index, data.next_turn(), true);
+                self.draw_mark(ctx, env, index, data.next_turn(), true);
             }
         }
 
         for x in 0..3_usize {
             for y in 0..3_usize {
                 if let Some(mark) = data[(x, y)] {
-                    self.draw_mark(ctx, (x, y), mark, false);
+                    self.draw_mark(ctx, env, (x, y), mark, false);
                 }
             }
         }
 
+        let winning = self.winning.eased();
+
         if let Some(mark) = self.won {
             let shape = ctx.size()
                 .to_rect()
                 .inset(20.0)
                 .to_rounded_rect(slot_size / 3.0);
-            let brush = ctx.solid_brush(env.get(druid::theme::WINDOW_BACKGROUND_COLOR).with_alpha(0.7));
+            // A won field which is still played in stays readable.
+            let strength = if data.has_free() {0.4} else {1.0};
+            let brush = ctx.solid_brush(faded(env.get(WON_FIELD_COLOR), winning * strength));
 
             ctx.fill(shape, &brush);
 
             let bounds = ctx.size().to_rect().inset(-line_width);
             let line_width = bounds.width() / 10.0;
 
-            draw_mark(ctx, bounds.inset(-line_width), line_width, 1.0, mark);
+            draw_mark_partly(ctx, env, bounds.inset(-line_width), line_width, strength, mark, winning);
+        }
+
+        let mut stroke_style = StrokeStyle::new();
+        stroke_style.line_cap = Some(LineCap::Round);
+
+        // The three slots which won the field.
+        if let (Some(mark), Some(line)) = (self.won, data.winning_line()) {
+            let (dx, dy) = direction(line);
+            let start = slot_rect(line[0], slot_size).center() - (dx * slot_size / 3.0, dy * slot_size / 3.0);
+            let end = slot_rect(line[2], slot_size).center() + (dx * slot_size / 3.0, dy * slot_size / 3.0);
+            let brush = ctx.solid_brush(mark_color(env, mark).with_alpha(0.6 * winning));
+
+            ctx.stroke_styled(Line::new(start, end), &brush, line_width * 3.0, &stroke_style);
+        }
+
+        // This field's part of the line through the three fields which won the game.
+        if let (Some(mark), Some(line)) = (self.won, data.board_line()) {
+            let (dx, dy) = direction(line);
+            let center = ctx.size().to_rect().center();
+            // The end fields of the line only get a short part beyond their center.
+            let reach = size / 2.0 + FIELD_SPACING / 2.0;
+            let back = if line[0] == data.position() {slot_size} else {reach};
+            let forward = if line[2] == data.position() {slot_size} else {reach};
+            let brush = ctx.solid_brush(mark_color(env, mark).with_alpha(0.8 * winning));
+
+            ctx.stroke_styled(
+                Line::new(center - (dx * back, dy * back), center + (dx * forward, dy * forward)),
+                &brush,
+                size / 15.0,
+                &stroke_style,
+            );
+        }
+
+        // The focus ring of the keyboard cursor.
+        if let Some(index) = data.cursor() {
+            let shape = slot_rect(index, slot_size)
+                .inset(-line_width * 2.0)
+                .to_rounded_rect(slot_size / 6.0);
+            let brush = ctx.solid_brush(env.get(druid::theme::PRIMARY_LIGHT));
+
+            ctx.stroke(shape, &brush, line_width * 2.0);
+        }
+    }
+}
+
+/// The nine small fields, laid out with `FIELD_SPACING` between them. The highlight of
+/// the fields the next move may go to is drawn below the fields, it slides from one
+/// field to the next when both moves are bound to a field and fades otherwise.
+pub struct Board {
+    fields: Vec<WidgetPod<GameData, Box<dyn Widget<GameData>>>>,
+    /// The fields which were active before the last change of the highlight.
+    was_active: Vec<FieldPosition>,
+    highlight: Progress,
+}
+
+impl Board {
+    /// `fields` are the small fields in the order of `FieldPosition::all`.
+    pub fn new(fields: impl IntoIterator<Item=Box<dyn Widget<GameData>>>) -> Self {
+        Board {
+            fields: fields.into_iter().map(WidgetPod::new).collect(),
+            was_active: Vec::new(),
+            highlight: Progress::done(HIGHLIGHT_TIME),
+        }
+    }
+
+    fn field_rect(&self, position: FieldPosition) -> Rect {
+        self.fields[position.index()].layout_rect()
+    }
+
+    fn fill_highlight(&self, ctx: &mut PaintCtx, env: &Env, rect: Rect, alpha: f64) {
+        let shape = rect.inset(20.0).to_rounded_rect(rect.width() / 9.0);
+        let brush = ctx.solid_brush(faded(env.get(ACTIVE_FIELD_COLOR), alpha));
+
+        ctx.fill(shape, &brush);
+    }
+}
+
+fn active_fields(data: &GameData) -> Vec<FieldPosition> {
+    FieldPosition::all().filter(|field|data.is_playable(*field)).collect()
+}
+
+impl Widget<GameData> for Board {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut GameData, env: &Env) {
+        if let Event::AnimFrame(interval) = event {
+            self.highlight.advance(*interval as f64 / 1_000_000_000.0);
+            ctx.request_paint();
+            if self.highlight.is_running() {
+                ctx.request_anim_frame();
+            }
+        }
+        for field in &mut self.fields {
+            field.event(ctx, event, data, env);
         }
     }
-}
\ No newline at end of file
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &GameData, env: &Env) {
+        for field in &mut self.fields {
+            field.lifecycle(ctx, event, data, env);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &GameData, data: &GameData, env: &Env) {
+        for field in &mut self.fields {
+            field.update(ctx, data, env);
+        }
+
+        let was_active = active_fields(old_data);
+        if was_active != active_fields(data) {
+            self.was_active = was_active;
+            if data.animations {
+                self.highlight.restart();
+                ctx.request_anim_frame();
+            } else {
+                self.highlight.finish();
+            }
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &GameData, env: &Env) -> Size {
+        let size = bc.max();
+        let field_size = ((size.width.min(size.height) - FIELD_SPACING * 2.0) / 3.0).max(0.0);
+        let board_size = field_size * 3.0 + FIELD_SPACING * 2.0;
+        let origin = Point::new((size.width - board_size) / 2.0, (size.height - board_size) / 2.0);
+
+        let mut paint_rect = Rect::ZERO;
+        for (position, field) in FieldPosition::all().zip(&mut self.fields) {
+            field.layout(ctx, &BoxConstraints::tight(Size::new(field_size, field_size)), data, env);
+            let offset = Vec2::new(position.x() as f64, position.y() as f64) * (field_size + FIELD_SPACING);
+            field.set_origin(ctx, data, env, origin + offset);
+            paint_rect = paint_rect.union(field.paint_rect());
+        }
+        ctx.set_paint_insets(paint_rect - size.to_rect());
+
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &GameData, env: &Env) {
+        let active = active_fields(data);
+        let progress = self.highlight.eased();
+
+        match (self.was_active.as_slice(), active.as_slice()) {
+            ([from], [to]) if self.highlight.is_running() => {
+                let (from, to) = (self.field_rect(*from), self.field_rect(*to));
+                let rect = Rect::from_origin_size(from.origin().lerp(to.origin(), progress), to.size());
+                self.fill_highlight(ctx, env, rect, 1.0);
+            }
+            _ => {
+                for field in &active {
+                    let alpha = if self.was_active.contains(field) {1.0} else {progress};
+                    self.fill_highlight(ctx, env, self.field_rect(*field), alpha);
+                }
+                if self.highlight.is_running() {
+                    for field in self.was_active.iter().filter(|field|!active.contains(field)) {
+                        self.fill_highlight(ctx, env, self.field_rect(*field), 1.0 - progress);
+                    }
+                }
+            }
+        }
+
+        for field in &mut self.fields {
+            field.paint(ctx, data, env);
+        }
+    }
+}
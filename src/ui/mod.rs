@@ -1,86 +1,500 @@
-use druid::{Widget, Lens, WidgetExt, Color, RenderContext, Data, UpdateCtx, Env};
-use crate::data::{GameData, FieldMeta, Slot};
-use crate::ui::field::{FieldWidget, draw_mark};
+use druid::{Widget, Lens, LensExt, WidgetExt, Color, RenderContext, Data, UpdateCtx, Env, EventCtx, Event, TimerToken, LifeCycle, LifeCycleCtx, KeyEvent, KbKey, Selector};
+use crate::data::{GameData, FieldMeta, FieldPosition, Mark, Ending};
+use crate::clock::{TimeControl, format_time};
+use crate::series::{GAME_OVER, MAX_SERIES_LENGTH};
+use crate::ai::solver::Outcome;
+use crate::ai::{MIN_LEVEL, MAX_LEVEL};
+use crate::network::{host, join, watch};
+use crate::ui::field::{FieldWidget, Board, draw_mark};
+use crate::ui::settings::settings_ui;
 use druid::lens::Map;
-use druid::widget::{Flex, Painter, Label, CrossAxisAlignment, MainAxisAlignment, Controller};
+use druid::widget::{Flex, Painter, Label, CrossAxisAlignment, LineBreaking, Controller, Stepper, Checkbox, TextBox, Either};
 use druid::piet::{Text, TextLayoutBuilder, TextLayout};
+use std::time::{Duration, Instant};
 
 mod field;
+mod settings;
+mod theme;
 
 struct Client;
 
 impl<W: Widget<GameData>> Controller<GameData, W> for Client {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GameData, env: &Env) {
+        if let Event::WindowConnected = event {
+            // The first game was started before the window, the AI may have to move first.
+            data.handle_opponent(ctx.get_external_handle());
+        }
+        child.event(ctx, event, data, env);
+    }
+
     fn update(&mut self, child: &mut W, ctx: &mut UpdateCtx, old_data: &GameData, data: &GameData, env: &Env) {
         child.update(ctx, old_data, data, env);
-        data.handle_opponent(ctx.get_external_handle());
+        let moved = !old_data.game.same(&data.game) || old_data.next_turn != data.next_turn;
+        let new_players = !old_data.cross.same(&data.cross) || !old_data.circle.same(&data.circle);
+        let interrupted = old_data.ending != data.ending || old_data.is_paused() != data.is_paused();
+
+        if moved || new_players || interrupted {
+            data.handle_opponent(ctx.get_external_handle());
+        }
+        if moved {
+            data.handle_analysis(ctx.get_external_handle());
+        }
+        if !old_data.is_over() && data.is_over() {
+            ctx.submit_command(GAME_OVER);
+        }
+    }
+}
+
+/// How often the clocks are updated.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Runs the clocks of the game.
+struct ClockTicker {
+    timer: TimerToken,
+    last_tick: Instant,
+}
+
+impl ClockTicker {
+    fn new() -> Self {
+        ClockTicker {
+            timer: TimerToken::INVALID,
+            last_tick: Instant::now(),
+        }
+    }
+}
+
+impl<W: Widget<GameData>> Controller<GameData, W> for ClockTicker {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GameData, env: &Env) {
+        match event {
+            Event::WindowConnected => {
+                self.last_tick = Instant::now();
+                self.timer = ctx.request_timer(TICK);
+            }
+            Event::Timer(token) if *token == self.timer => {
+                let now = Instant::now();
+                data.tick_clock(now.duration_since(self.last_tick).as_millis() as u64);
+                self.last_tick = now;
+                self.timer = ctx.request_timer(TICK);
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// Keeps the size of the window, for the settings.
+struct WindowSize;
+
+impl<W: Widget<GameData>> Controller<GameData, W> for WindowSize {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GameData, env: &Env) {
+        if let Event::WindowSize(size) = event {
+            data.window_size = *size;
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// The board lost the focus, the keyboard cursor is hidden.
+const HIDE_CURSOR: Selector = Selector::new("de.ticktacktoe_ultimate.hide_cursor");
+
+/// Plays with the keyboard. The arrows move the cursor by a slot, with shift by a field,
+/// and enter or space places the mark. The digits pick the field and then the slot,
+/// laid out like the number pad, `7` is the top left. When the field is given, the
+/// first digit picks the slot already.
+struct Keyboard {
+    /// The field picked by the first digit.
+    picked: Option<FieldPosition>,
+}
+
+impl Keyboard {
+    fn new() -> Self {
+        Keyboard {
+            picked: None,
+        }
+    }
+
+    /// Handles a key for the board, returns whether it was used.
+    fn key(&mut self, key: &KeyEvent, data: &mut GameData) -> bool {
+        if data.cursor.is_none() {
+            // The first key only shows where the cursor is.
+            data.show_cursor();
+            return true;
+        }
+        let step = if key.mods.shift() {3} else {1};
+        let picked = self.picked.take();
+
+        match &key.key {
+            KbKey::ArrowLeft => data.move_cursor(-step, 0),
+            KbKey::ArrowRight => data.move_cursor(step, 0),
+            KbKey::ArrowUp => data.move_cursor(0, -step),
+            KbKey::ArrowDown => data.move_cursor(0, step),
+            KbKey::Enter => data.play_cursor(),
+            KbKey::Character(text) if text == " " => data.play_cursor(),
+            KbKey::Character(text) => match numpad_position(text) {
+                Some(position) => match (data.next_field, picked) {
+                    (Some(field), _) | (None, Some(field)) => data.cursor = Some((field, position)),
+                    (None, None) => {
+                        let inner = data.cursor.map_or(FieldPosition::center(), |(_, inner)|inner);
+                        data.cursor = Some((position, inner));
+                        self.picked = Some(position);
+                    }
+                },
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
     }
 }
 
-fn position_lens(x: usize, y: usize) -> impl Lens<GameData, FieldMeta> {
-    let position = (x, y);
+/// The position of a digit on the number pad.
+fn numpad_position(text: &str) -> Option<FieldPosition> {
+    let digit = text.parse::<usize>().ok().filter(|digit|(1..=9).contains(digit))?;
+    Some(FieldPosition::from(((digit - 1) % 3, 2 - (digit - 1) / 3)))
+}
+
+impl<W: Widget<GameData>> Controller<GameData, W> for Keyboard {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GameData, env: &Env) {
+        match event {
+            Event::WindowConnected => ctx.request_focus(),
+            Event::MouseDown(_) => ctx.request_focus(),
+            Event::Command(cmd) if cmd.is(HIDE_CURSOR) => {
+                data.hide_cursor();
+                self.picked = None;
+                ctx.set_handled();
+                return;
+            }
+            Event::KeyDown(key) if ctx.is_focused() && key.key == KbKey::Tab => {
+                if key.mods.shift() {
+                    ctx.focus_prev();
+                } else {
+                    ctx.focus_next();
+                }
+                ctx.set_handled();
+                return;
+            }
+            Event::KeyDown(key) if ctx.is_focused() => {
+                let handled = self.key(key, data);
+                if handled {
+                    ctx.set_handled();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, child: &mut W, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &GameData, env: &Env) {
+        match event {
+            LifeCycle::WidgetAdded => ctx.register_for_focus(),
+            LifeCycle::FocusChanged(false) => ctx.submit_command(HIDE_CURSOR.to(ctx.widget_id())),
+            _ => {}
+        }
+        child.lifecycle(ctx, event, data, env);
+    }
+}
+
+fn position_lens(position: FieldPosition) -> impl Lens<GameData, FieldMeta> {
     Map::new(
         move|game_data|FieldMeta::from_data(game_data, position),
         move|game_data, field_meta|field_meta.write_back(game_data, position)
     )
 }
 
-pub fn row(y: usize) -> impl Widget<GameData> {
+fn level_lens(mark: Mark) -> impl Lens<GameData, f64> {
+    Map::new(
+        move|game_data: &GameData|game_data.level(mark) as f64,
+        move|game_data: &mut GameData, level: f64|game_data.set_level(mark, level.round() as u64)
+    )
+}
+
+fn level_setting(mark: Mark) -> impl Widget<GameData> {
     Flex::row()
-        .main_axis_alignment(MainAxisAlignment::Center)
-        .must_fill_main_axis(true)
-        .with_flex_child(FieldWidget::new().lens(position_lens(0, y)), 1.0)
-        .with_spacer(60.0)
-        .with_flex_child(FieldWidget::new().lens(position_lens(1, y)), 1.0)
-        .with_spacer(60.0)
-        .with_flex_child(FieldWidget::new().lens(position_lens(2, y)), 1.0)
+        .with_child(Painter::new(move|ctx, _: &GameData, env|{
+            draw_mark(ctx, env, ctx.size().to_rect().inset(-3.0), 3.0, 1.0, mark);
+        }).fix_size(20.0, 20.0))
+        .with_spacer(5.0)
+        .with_child(
+            Label::dynamic(move|data: &GameData, _|format!("Level {}", data.level(mark)))
+                .fix_width(60.0)
+        )
+        .with_child(
+            Stepper::new()
+                .with_range(MIN_LEVEL as f64, MAX_LEVEL as f64)
+                .with_step(1.0)
+                .lens(level_lens(mark))
+        )
+}
+
+/// The remaining time of `mark`, empty without clocks.
+fn clock_display(mark: Mark) -> impl Widget<GameData> {
+    Flex::row()
+        .with_child(Painter::new(move|ctx, data: &GameData, env|{
+            if data.clocks.is_some() {
+                draw_mark(ctx, env, ctx.size().to_rect().inset(-3.0), 3.0, 1.0, mark);
+            }
+        }).fix_size(20.0, 20.0))
+        .with_spacer(5.0)
+        .with_child(
+            Label::dynamic(move|data: &GameData, _|{
+                data.clocks.map_or(String::new(), |clocks|format_time(clocks.remaining(mark)))
+            })
+            .with_text_size(20.0)
+            .fix_width(60.0)
+        )
+}
+
+fn time_setting(label: &'static str, lens: impl Lens<TimeControl, u64> + 'static, min: f64, max: f64) -> impl Widget<GameData> {
+    Flex::row()
+        .with_child(Label::new(label))
+        .with_child(
+            Stepper::new()
+                .with_range(min, max)
+                .with_step(1.0)
+                .lens(GameData::time_control.then(lens).map(
+                    |time: &u64|*time as f64,
+                    |time: &mut u64, value: f64|*time = value.round() as u64,
+                ))
+        )
 }
 
 pub fn main_ui() -> impl Widget<GameData> {
     let header = Flex::row()
-        .with_child(Painter::new(|ctx, data: &GameData, _|{
-            let mark = data.game.belongs_to().unwrap_or(data.next_turn);
-            draw_mark(ctx, ctx.size().to_rect().inset(-4.0), 4.0, 1.0, mark);
+        .with_child(Painter::new(|ctx, data: &GameData, env|{
+            if data.is_over() && data.winner().is_none() {
+                return;
+            }
+            let mark = data.winner().unwrap_or(data.next_turn);
+            draw_mark(ctx, env, ctx.size().to_rect().inset(-4.0), 4.0, 1.0, mark);
 
         }).fix_size(30.0, 30.0))
         .with_child(Label::dynamic(|a: &GameData, _|{
-            if a.game.belongs_to().is_some() {
-                String::from("won the Game!")
-            } else {
-                String::from("'s turn")
+            match (a.winner(), a.ending) {
+                (Some(_), Some(Ending::Timeout(_))) => String::from("won on time!"),
+                (Some(_), Some(Ending::Resigned(_))) => String::from("won by resignation!"),
+                (Some(_), _) => String::from("won the Game!"),
+                (None, Some(Ending::DrawAgreed)) => String::from("Draw agreed"),
+                (None, _) if a.is_over() => String::from("Draw!"),
+                _ if a.is_paused() => String::from("'s turn, paused"),
+                _ => String::from("'s turn"),
             }
-        }).with_text_size(20.0));
+        }).with_text_size(20.0))
+        .with_spacer(30.0)
+        .with_child(clock_display(Mark::Cross))
+        .with_spacer(10.0)
+        .with_child(clock_display(Mark::Circle));
+
+    let analysis = Label::dynamic(|data: &GameData, _|{
+        if let Some(message) = &data.message {
+            return message.clone();
+        }
+        match data.analysis {
+            Some(solution) => match solution.outcome {
+                Outcome::Win => format!("{} has a forced win in {}", data.next_turn, solution.moves()),
+                Outcome::Loss => format!("{} has a forced win in {}", data.next_turn.other(), solution.moves()),
+                Outcome::Draw => String::from("Draw with perfect play"),
+            },
+            None => String::new(),
+        }
+    });
 
-    let footer = Flex::row()
+    // Spells out the board while it is played with the keyboard.
+    let description = Label::dynamic(|data: &GameData, _|{
+        if data.cursor.is_some() {data.describe()} else {String::new()}
+    })
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    let scoreboard = Label::dynamic(|data: &GameData, _|{
+        data.series.as_ref().map_or(String::new(), |series|series.scoreboard())
+    });
+
+    let actions = Flex::row()
         .with_child(
-            colored_button(Color::GREEN, "AI Easy", |data: &mut GameData|*data = GameData::ai(1))
+            colored_button(Color::GREEN, "Resign", |_, data: &mut GameData|data.resign())
         )
         .with_spacer(10.0)
         .with_child(
-            colored_button(Color::GREEN, "AI Hard", |data: &mut GameData|*data = GameData::ai(2))
+            colored_button(Color::GREEN, "Draw", |_, data: &mut GameData|data.offer_draw())
         )
         .with_spacer(10.0)
         .with_child(
-            colored_button(Color::GREEN, "2 Players", |data: &mut GameData|*data = GameData::local())
+            colored_button(Color::GREEN, "Rematch", |_, data: &mut GameData|data.rematch())
         );
 
+    let new_game = Flex::row()
+        .with_child(
+            colored_button(Color::GREEN, "AI", |_, data: &mut GameData|data.restart_ai())
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "AI vs AI", |_, data: &mut GameData|data.restart_ai_vs_ai())
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Engine", |_, data: &mut GameData|data.restart_external())
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "2 Players", |_, data: &mut GameData|data.restart(None, None))
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Settings", |_, data: &mut GameData|data.show_settings = !data.show_settings)
+        );
+
+    let ai_settings = Flex::row()
+        .with_child(level_setting(Mark::Cross))
+        .with_spacer(20.0)
+        .with_child(level_setting(Mark::Circle))
+        .with_spacer(20.0)
+        .with_child(Label::new("AI plays"))
+        .with_spacer(5.0)
+        .with_child(
+            Painter::new(|ctx, data: &GameData, env|{
+                let alpha = if ctx.is_hot() {0.6} else {1.0};
+                draw_mark(ctx, env, ctx.size().to_rect().inset(-3.0), 3.0, alpha, data.ai_mark);
+            })
+            .fix_size(20.0, 20.0)
+            .on_click(|_, data: &mut GameData, _|data.ai_mark = data.ai_mark.other())
+        )
+        .with_spacer(20.0)
+        .with_child(Checkbox::new("Ponder").lens(GameData::ponder));
+
+    let engine_settings = Flex::row()
+        .with_child(Label::new("Engine command"))
+        .with_spacer(5.0)
+        .with_flex_child(TextBox::new().expand_width().lens(GameData::engine_command), 1.0);
+
+    let network = Flex::row()
+        .with_child(Label::new("Address"))
+        .with_spacer(5.0)
+        .with_flex_child(TextBox::new().expand_width().lens(GameData::address), 1.0)
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Host", |ctx, data: &mut GameData|{
+                host(&data.address, ctx.get_external_handle());
+                data.message = Some(String::from("Waiting for an opponent"));
+            })
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Join", |ctx, data: &mut GameData|{
+                join(&data.address, ctx.get_external_handle());
+                data.message = Some(format!("Connecting to {}", data.address));
+            })
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Watch", |ctx, data: &mut GameData|{
+                watch(&data.address, ctx.get_external_handle());
+                data.message = Some(format!("Connecting to {}", data.address));
+            })
+        );
+
+    let clock_settings = Flex::row()
+        .with_child(
+            Label::dynamic(|data: &GameData, _|data.time_control.describe())
+                .fix_width(120.0)
+                .on_click(|_, data: &mut GameData, _|data.time_control.mode = data.time_control.mode.next())
+        )
+        .with_spacer(10.0)
+        .with_child(time_setting("min", TimeControl::base, 1.0, 60.0))
+        .with_spacer(10.0)
+        .with_child(time_setting("+s", TimeControl::increment, 0.0, 60.0))
+        .with_spacer(10.0)
+        .with_child(time_setting("s/move", TimeControl::move_time, 1.0, 300.0))
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Pause", |_, data: &mut GameData|data.toggle_pause())
+        );
+
+    let series_settings = Flex::row()
+        .with_child(
+            Label::dynamic(|data: &GameData, _|format!("Best of {}", data.series_length))
+                .fix_width(70.0)
+        )
+        .with_child(
+            Stepper::new()
+                .with_range(1.0, MAX_SERIES_LENGTH as f64)
+                .with_step(2.0)
+                .lens(GameData::series_length.map(
+                    |length: &u64|*length as f64,
+                    |length: &mut u64, value: f64|*length = value.round() as u64,
+                ))
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Series", |_, data: &mut GameData|data.start_series())
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Next", |_, data: &mut GameData|data.next_game())
+        );
+
+    let board = Board::new(FieldPosition::all().map(|position|FieldWidget::new().lens(position_lens(position)).boxed()))
+        .controller(Keyboard::new());
+
+    let summary = Flex::column()
+        .with_child(
+            Label::dynamic(|data: &GameData, _|{
+                data.series.as_ref().map_or(String::new(), |series|series.summary())
+            })
+            .with_text_size(20.0)
+        )
+        .with_spacer(20.0)
+        .with_child(
+            colored_button(Color::GREEN, "Close", |_, data: &mut GameData|data.series = None)
+        )
+        .center();
+
+    let board = Either::new(
+        |data: &GameData, _|data.series.as_ref().is_some_and(|series|series.is_finished()),
+        summary,
+        board,
+    );
+
+    let board = Either::new(|data: &GameData, _|data.show_settings, settings_ui(), board);
+
+    let footer = Flex::column()
+        .with_child(actions)
+        .with_spacer(10.0)
+        .with_child(new_game)
+        .with_spacer(10.0)
+        .with_child(ai_settings)
+        .with_spacer(10.0)
+        .with_child(engine_settings)
+        .with_spacer(10.0)
+        .with_child(series_settings)
+        .with_spacer(10.0)
+        .with_child(clock_settings)
+        .with_spacer(10.0)
+        .with_child(network);
+
     Flex::column()
         .cross_axis_alignment(CrossAxisAlignment::Center)
         .with_spacer(20.0)
         .with_child(header)
-        .with_spacer(30.0)
-        .with_flex_child(row(0), 1.0)
-        .with_spacer(60.0)
-        .with_flex_child(row(1), 1.0)
-        .with_spacer(60.0)
-        .with_flex_child(row(2), 1.0)
+        .with_spacer(5.0)
+        .with_child(analysis)
+        .with_spacer(5.0)
+        .with_child(description)
+        .with_spacer(5.0)
+        .with_child(scoreboard)
+        .with_spacer(10.0)
+        .with_flex_child(board, 1.0)
         .with_spacer(40.0)
         .with_child(footer)
         .with_spacer(10.0)
         .padding((40.0, 0.0))
+        .background(druid::theme::WINDOW_BACKGROUND_COLOR)
+        .env_scope(|env, data: &GameData|theme::apply(data.theme, env))
         .controller(Client)
+        .controller(ClockTicker::new())
+        .controller(WindowSize)
 }
 
-fn colored_button<T: Data>(color: Color, string: &'static str, f: impl Fn(&mut T) + 'static) -> impl Widget<T> {
+fn colored_button<T: Data>(color: Color, string: &'static str, f: impl Fn(&mut EventCtx, &mut T) + 'static) -> impl Widget<T> {
     let mut text = None;
 
 
@@ -116,5 +530,5 @@ fn colored_button<T: Data>(color: Color, string: &'static str, f: impl Fn(&mut T
         ctx.draw_text(text.as_ref().unwrap(), ((90.0 - text.as_ref().unwrap().size().width) / 2.0, 3.0));
     })
     .fix_size(90.0, 35.0)
-    .on_click(move |_, data: &mut T, _|f(data))
+    .on_click(move |ctx, data: &mut T, _|f(ctx, data))
 }
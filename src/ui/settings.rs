@@ -0,0 +1,121 @@
+use druid::{Widget, WidgetExt, LensExt, Color};
+use druid::widget::{Flex, Label, RadioGroup, Checkbox, Stepper, CrossAxisAlignment};
+use crate::data::{GameData, Mark, StartOpponent, Rules};
+use crate::settings::{Settings, MAX_AI_DELAY};
+use crate::theme::Theme;
+use crate::ui::{level_setting, colored_button};
+
+/// The steps of the AI delay in milliseconds.
+const AI_DELAY_STEP: f64 = 100.0;
+
+fn section(title: &'static str, content: impl Widget<GameData> + 'static) -> impl Widget<GameData> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(title).with_text_size(18.0))
+        .with_spacer(5.0)
+        .with_child(content)
+}
+
+/// Shown instead of the board, the settings are used right away and `Save` keeps them
+/// for the next start.
+pub fn settings_ui() -> impl Widget<GameData> {
+    let opponent = RadioGroup::new(vec![
+        ("2 Players", StartOpponent::TwoPlayers),
+        ("AI", StartOpponent::Ai),
+        ("Engine", StartOpponent::Engine),
+    ]).lens(GameData::start_opponent);
+
+    let ai_mark = Flex::row()
+        .with_child(Label::new("AI plays"))
+        .with_spacer(5.0)
+        .with_child(
+            RadioGroup::new(vec![("Cross", Mark::Cross), ("Circle", Mark::Circle)])
+                .lens(GameData::ai_mark)
+        );
+
+    let strength = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(level_setting(Mark::Cross))
+        .with_spacer(5.0)
+        .with_child(level_setting(Mark::Circle));
+
+    let delay = Flex::row()
+        .with_child(
+            Label::dynamic(|data: &GameData, _|format!("At least {} ms per move", data.ai_delay))
+                .fix_width(180.0)
+        )
+        .with_child(
+            Stepper::new()
+                .with_range(0.0, MAX_AI_DELAY as f64)
+                .with_step(AI_DELAY_STEP)
+                .lens(GameData::ai_delay.map(
+                    |delay: &u64|*delay as f64,
+                    |delay: &mut u64, value: f64|*delay = value.round() as u64,
+                ))
+        );
+
+    let theme = RadioGroup::new(vec![
+        (Theme::Classic.name(), Theme::Classic),
+        (Theme::Dark.name(), Theme::Dark),
+        (Theme::Light.name(), Theme::Light),
+        (Theme::ColorBlind.name(), Theme::ColorBlind),
+    ]).lens(GameData::theme);
+
+    let rules = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            RadioGroup::new(vec![
+                ("Won fields are closed", Rules::Standard),
+                ("Won fields stay open", Rules::OpenFields),
+            ]).lens(GameData::rules)
+        )
+        .with_spacer(5.0)
+        .with_child(
+            Label::new("Network games and engines use closed fields")
+                .with_text_size(12.0)
+        );
+
+    let buttons = Flex::row()
+        .with_child(
+            colored_button(Color::GREEN, "Save", |_, data: &mut GameData|{
+                data.message = Some(match Settings::from_data(data).save() {
+                    Ok(path) => format!("Saved the settings to {}", path.display()),
+                    Err(err) => format!("Could not save the settings: {}", err),
+                });
+            })
+        )
+        .with_spacer(10.0)
+        .with_child(
+            colored_button(Color::GREEN, "Close", |_, data: &mut GameData|data.show_settings = false)
+        );
+
+    let left = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(section("Opponent at the start", opponent))
+        .with_spacer(20.0)
+        .with_child(section("AI strength", strength))
+        .with_spacer(10.0)
+        .with_child(ai_mark)
+        .with_spacer(20.0)
+        .with_child(section("AI move delay", delay));
+
+    let right = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(section("Theme", theme))
+        .with_spacer(20.0)
+        .with_child(section("Board", Checkbox::new("Animations").lens(GameData::animations)))
+        .with_spacer(20.0)
+        .with_child(section("Rules of the next game", rules));
+
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(left)
+                .with_spacer(40.0)
+                .with_child(right)
+        )
+        .with_spacer(30.0)
+        .with_child(buttons)
+        .center()
+}
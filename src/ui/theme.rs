@@ -0,0 +1,76 @@
+use crate::data::Mark;
+use crate::theme::Theme;
+use druid::{Color, Env, Key};
+use druid::theme;
+
+pub const CROSS_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.cross_color");
+pub const CIRCLE_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.circle_color");
+pub const GRID_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.grid_color");
+/// The background of the fields the next move may go to.
+pub const ACTIVE_FIELD_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.active_field_color");
+/// Laid over the marks of a won field, it should be partly transparent.
+pub const WON_FIELD_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.won_field_color");
+/// Behind the slot of the last move, it should be partly transparent.
+pub const LAST_MOVE_COLOR: Key<Color> = Key::new("de.ticktacktoe_ultimate.last_move_color");
+
+/// Sets the colors of `theme` in `env`.
+pub fn apply(theme: Theme, env: &mut Env) {
+    match theme {
+        Theme::Classic => {
+            env.set(CROSS_COLOR, Color::RED);
+            env.set(CIRCLE_COLOR, Color::BLUE);
+            env.set(GRID_COLOR, Color::BLACK);
+            env.set(ACTIVE_FIELD_COLOR, env.get(theme::BACKGROUND_LIGHT));
+            env.set(WON_FIELD_COLOR, env.get(theme::WINDOW_BACKGROUND_COLOR).with_alpha(0.7));
+            env.set(LAST_MOVE_COLOR, Color::rgb8(0xff, 0xd7, 0x00).with_alpha(0.25));
+        }
+        Theme::Dark => {
+            let background = Color::rgb8(0x12, 0x12, 0x12);
+
+            env.set(theme::WINDOW_BACKGROUND_COLOR, background.clone());
+            env.set(theme::BACKGROUND_LIGHT, Color::rgb8(0x24, 0x24, 0x24));
+            env.set(theme::BACKGROUND_DARK, Color::rgb8(0x1a, 0x1a, 0x1a));
+            env.set(CROSS_COLOR, Color::rgb8(0xff, 0x5c, 0x5c));
+            env.set(CIRCLE_COLOR, Color::rgb8(0x5c, 0x9d, 0xff));
+            env.set(GRID_COLOR, Color::rgb8(0x70, 0x70, 0x70));
+            env.set(ACTIVE_FIELD_COLOR, Color::rgb8(0x26, 0x26, 0x26));
+            env.set(WON_FIELD_COLOR, background.with_alpha(0.75));
+            env.set(LAST_MOVE_COLOR, Color::rgb8(0xff, 0xd7, 0x00).with_alpha(0.2));
+        }
+        Theme::Light => {
+            let background = Color::rgb8(0xf4, 0xf4, 0xf0);
+
+            env.set(theme::WINDOW_BACKGROUND_COLOR, background.clone());
+            env.set(theme::LABEL_COLOR, Color::rgb8(0x20, 0x20, 0x20));
+            env.set(theme::BACKGROUND_LIGHT, Color::WHITE);
+            env.set(theme::BACKGROUND_DARK, Color::rgb8(0xe4, 0xe4, 0xe0));
+            env.set(theme::FOREGROUND_DARK, Color::rgb8(0x40, 0x40, 0x40));
+            env.set(theme::BUTTON_DARK, Color::rgb8(0xc8, 0xc8, 0xc4));
+            env.set(theme::BUTTON_LIGHT, Color::rgb8(0xe8, 0xe8, 0xe4));
+            env.set(theme::BORDER_DARK, Color::rgb8(0xb0, 0xb0, 0xb0));
+            env.set(theme::BORDER_LIGHT, Color::rgb8(0x60, 0x60, 0x60));
+            env.set(theme::CURSOR_COLOR, Color::BLACK);
+            env.set(CROSS_COLOR, Color::rgb8(0xd0, 0x20, 0x20));
+            env.set(CIRCLE_COLOR, Color::rgb8(0x20, 0x40, 0xd0));
+            env.set(GRID_COLOR, Color::rgb8(0x30, 0x30, 0x30));
+            env.set(ACTIVE_FIELD_COLOR, Color::rgb8(0xdc, 0xe8, 0xd8));
+            env.set(WON_FIELD_COLOR, background.with_alpha(0.7));
+            env.set(LAST_MOVE_COLOR, Color::rgb8(0xff, 0xc0, 0x00).with_alpha(0.35));
+        }
+        Theme::ColorBlind => {
+            env.set(CROSS_COLOR, Color::rgb8(0xe6, 0x9f, 0x00));
+            env.set(CIRCLE_COLOR, Color::rgb8(0x56, 0xb4, 0xe9));
+            env.set(GRID_COLOR, Color::rgb8(0xa0, 0xa0, 0xa0));
+            env.set(ACTIVE_FIELD_COLOR, env.get(theme::BACKGROUND_LIGHT));
+            env.set(WON_FIELD_COLOR, env.get(theme::WINDOW_BACKGROUND_COLOR).with_alpha(0.7));
+            env.set(LAST_MOVE_COLOR, Color::WHITE.with_alpha(0.2));
+        }
+    }
+}
+
+pub fn mark_color(env: &Env, mark: Mark) -> Color {
+    match mark {
+        Mark::Cross => env.get(CROSS_COLOR),
+        Mark::Circle => env.get(CIRCLE_COLOR),
+    }
+}